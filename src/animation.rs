@@ -0,0 +1,322 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    camera::Camera,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    ray::Ray,
+    util::degrees_to_radian,
+    vec3::{Point3, Vec3},
+};
+
+/// Values that keyframes can blend between.
+pub trait Interpolate: Copy {
+    /// `values[0] * weights[0] + ... + values[3] * weights[3]`.
+    fn weighted_sum(values: [Self; 4], weights: [f64; 4]) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn weighted_sum(values: [f64; 4], weights: [f64; 4]) -> f64 {
+        values.iter().zip(weights).map(|(v, w)| v * w).sum()
+    }
+}
+
+impl Interpolate for Vec3 {
+    fn weighted_sum(values: [Vec3; 4], weights: [f64; 4]) -> Vec3 {
+        values
+            .iter()
+            .zip(weights)
+            .fold(Vec3::zero(), |sum, (&v, w)| sum + w * v)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    /// Catmull-Rom spline through the keys, smooth across them.
+    CatmullRom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe<T> {
+    pub time: f64,
+    pub value: T,
+}
+
+/// A value changing over time, given at keyframes. It holds still before the
+/// first key and after the last.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track<T> {
+    pub keys: Vec<Keyframe<T>>,
+    pub interpolation: Interpolation,
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Track {
+            keys: Vec::new(),
+            interpolation,
+        }
+    }
+
+    /// Adds a key, keeping the keys in time order.
+    pub fn with_key(mut self, time: f64, value: T) -> Self {
+        let index = self.keys.partition_point(|key| key.time <= time);
+        self.keys.insert(index, Keyframe { time, value });
+        self
+    }
+
+    /// Value at `time`, or `None` if the track has no keys.
+    pub fn value_at(&self, time: f64) -> Option<T> {
+        let (first, last) = (self.keys.first()?, self.keys.last()?);
+        if time <= first.time {
+            return Some(first.value);
+        }
+        if time >= last.time {
+            return Some(last.value);
+        }
+
+        // Keys k1 and k2 surround `time`, with their neighbours k0 and k3
+        // repeated at the ends of the track.
+        let i = self.keys.partition_point(|key| key.time <= time) - 1;
+        let k0 = self.keys[i.saturating_sub(1)];
+        let k1 = self.keys[i];
+        let k2 = self.keys[i + 1];
+        let k3 = self.keys[(i + 2).min(self.keys.len() - 1)];
+        let dt = k2.time - k1.time;
+        let s = (time - k1.time) / dt;
+
+        let weights = match self.interpolation {
+            Interpolation::Linear => [0.0, 1.0 - s, s, 0.0],
+            Interpolation::CatmullRom => {
+                // Cubic Hermite segment with tangents taken from the
+                // neighbouring keys.
+                let (s2, s3) = (s * s, s * s * s);
+                let h00 = 2.0 * s3 - 3.0 * s2 + 1.0;
+                let h10 = s3 - 2.0 * s2 + s;
+                let h01 = -2.0 * s3 + 3.0 * s2;
+                let h11 = s3 - s2;
+                let a = h10 * dt / (k2.time - k0.time);
+                let b = h11 * dt / (k3.time - k1.time);
+                [-a, h00 - b, h01 + a, b]
+            }
+        };
+        Some(T::weighted_sum(
+            [k0.value, k1.value, k2.value, k3.value],
+            weights,
+        ))
+    }
+}
+
+/// Tracks for the camera's placement and lens. Settings without a track keep
+/// the camera's own value.
+#[derive(Debug, Clone, Default)]
+pub struct CameraAnimation {
+    pub look_from: Option<Track<Point3>>,
+    pub look_at: Option<Track<Point3>>,
+    pub vfov: Option<Track<f64>>,
+    pub focus_dist: Option<Track<f64>>,
+}
+
+impl CameraAnimation {
+    /// Poses `camera` as it is at `time`.
+    pub fn apply(&self, camera: &mut Camera, time: f64) {
+        let value = |track: &Option<Track<f64>>| track.as_ref().and_then(|t| t.value_at(time));
+        let point = |track: &Option<Track<Point3>>| track.as_ref().and_then(|t| t.value_at(time));
+        if let Some(look_from) = point(&self.look_from) {
+            camera.look_from = look_from;
+        }
+        if let Some(look_at) = point(&self.look_at) {
+            camera.look_at = look_at;
+        }
+        if let Some(vfov) = value(&self.vfov) {
+            camera.vfov = vfov;
+        }
+        if let Some(focus_dist) = value(&self.focus_dist) {
+            camera.focus_dist = focus_dist;
+        }
+        camera.time = time;
+    }
+}
+
+/// Moves an object over time: scaled uniformly about its origin, turned
+/// about the y axis by `rotation_y` degrees, then translated. The transform
+/// is taken at the time of each ray. The object disappears wherever a spline
+/// between scale keys overshoots to zero or below.
+pub struct Animated<H> {
+    object: H,
+    translation: Option<Track<Vec3>>,
+    rotation_y: Option<Track<f64>>,
+    scale: Option<Track<f64>>,
+}
+
+impl<H: Hittable> Animated<H> {
+    pub fn new(object: H) -> Self {
+        Animated {
+            object,
+            translation: None,
+            rotation_y: None,
+            scale: None,
+        }
+    }
+
+    pub fn with_translation(mut self, translation: Track<Vec3>) -> Self {
+        self.translation = Some(translation);
+        self
+    }
+
+    pub fn with_rotation_y(mut self, rotation_y: Track<f64>) -> Self {
+        self.rotation_y = Some(rotation_y);
+        self
+    }
+
+    /// Panics if a key of `scale` is not positive.
+    pub fn with_scale(mut self, scale: Track<f64>) -> Self {
+        assert!(
+            scale.keys.iter().all(|key| key.value > 0.0),
+            "scale keys must be positive"
+        );
+        self.scale = Some(scale);
+        self
+    }
+}
+
+impl<H: Hittable> Hittable for Animated<H> {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let time = ray.time;
+        let translation = self
+            .translation
+            .as_ref()
+            .and_then(|t| t.value_at(time))
+            .unwrap_or(Vec3::zero());
+        let angle = degrees_to_radian(
+            self.rotation_y
+                .as_ref()
+                .and_then(|t| t.value_at(time))
+                .unwrap_or(0.0),
+        );
+        let scale = self
+            .scale
+            .as_ref()
+            .and_then(|t| t.value_at(time))
+            .unwrap_or(1.0);
+        if scale <= 0.0 {
+            return None;
+        }
+        let (sin, cos) = angle.sin_cos();
+        let rotate = |v: Vec3| Vec3::new(cos * v.x + sin * v.z, v.y, -sin * v.x + cos * v.z);
+        let unrotate = |v: Vec3| Vec3::new(cos * v.x - sin * v.z, v.y, sin * v.x + cos * v.z);
+
+        // Scaling the direction along with the origin keeps ray parameters,
+        // and so `ray_t`, unchanged.
+        let local = ray.spawn(
+            unrotate(ray.origin - translation) / scale,
+            unrotate(ray.direction) / scale,
+        );
+        let mut rec = self.object.hit(&local, ray_t)?;
+        rec.p = scale * rotate(rec.p) + translation;
+        rec.normal = rotate(rec.normal);
+        rec.tangent = rotate(rec.tangent);
+        rec.bitangent = rotate(rec.bitangent);
+        Some(rec)
+    }
+}
+
+/// Frames of an animation to render, each written to
+/// `<prefix>.<frame number>.ppm`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameSequence {
+    pub prefix: PathBuf,
+    pub frames: Range<usize>,
+    pub frame_rate: f64,
+}
+
+impl FrameSequence {
+    pub fn new(prefix: impl Into<PathBuf>, frames: Range<usize>) -> Self {
+        FrameSequence {
+            prefix: prefix.into(),
+            frames,
+            frame_rate: 24.0,
+        }
+    }
+
+    /// Scene time at which `frame` is rendered.
+    pub fn time(&self, frame: usize) -> f64 {
+        frame as f64 / self.frame_rate
+    }
+
+    pub fn path(&self, frame: usize) -> PathBuf {
+        let mut path = numbered(&self.prefix, frame).into_os_string();
+        path.push(".ppm");
+        PathBuf::from(path)
+    }
+}
+
+/// `path` with the frame number appended, zero padded to four digits.
+pub fn numbered(path: &Path, frame: usize) -> PathBuf {
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(format!(".{frame:04}"));
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.unwrap();
+        assert!((actual - expected).abs() < 1e-12, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn empty_tracks_have_no_value() {
+        assert_eq!(Track::<f64>::new(Interpolation::Linear).value_at(0.0), None);
+    }
+
+    #[test]
+    fn linear_tracks_blend_neighbouring_keys() {
+        // Keys added out of order.
+        let track = Track::new(Interpolation::Linear)
+            .with_key(2.0, 5.0)
+            .with_key(0.0, 1.0)
+            .with_key(3.0, -1.0);
+        assert_close(track.value_at(0.0), 1.0);
+        assert_close(track.value_at(2.0), 5.0);
+        assert_close(track.value_at(3.0), -1.0);
+        assert_close(track.value_at(0.5), 2.0);
+        assert_close(track.value_at(2.25), 3.5);
+        assert_close(track.value_at(-1.0), 1.0);
+        assert_close(track.value_at(10.0), -1.0);
+    }
+
+    #[test]
+    fn catmull_rom_tracks_pass_smoothly_through_keys() {
+        // Samples of t^2, which the spline reproduces between inner keys.
+        let track = (0..4).fold(Track::new(Interpolation::CatmullRom), |track, t| {
+            let t = t as f64;
+            track.with_key(t, t * t)
+        });
+        for t in [0.0, 1.0, 2.0, 3.0] {
+            assert_close(track.value_at(t), t * t);
+        }
+        assert_close(track.value_at(1.5), 2.25);
+        assert_close(track.value_at(1.25), 1.5625);
+        assert_close(track.value_at(-2.0), 0.0);
+        assert_close(track.value_at(5.0), 9.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "scale keys must be positive")]
+    fn rejects_non_positive_scales() {
+        let scale = Track::new(Interpolation::Linear)
+            .with_key(0.0, 1.0)
+            .with_key(1.0, 0.0);
+        let _ = Animated::new(crate::hittable_list::HittableList {
+            objects: Vec::new(),
+        })
+        .with_scale(scale);
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{film::Film, vec3::Vec3};
+
+/// Arbitrary output variable: an extra image layer describing the first
+/// surface seen through each pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    /// Linear distance along the viewing axis, or from the camera center for
+    /// fisheye and panoramic cameras; infinite for the background.
+    Depth,
+    /// World-space normal facing the camera.
+    Normal,
+    Albedo,
+    /// World-space position.
+    Position,
+    MaterialId,
+    ObjectId,
+}
+
+impl Aov {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+        }
+    }
+
+    fn channels(&self) -> usize {
+        match self {
+            Aov::Depth | Aov::MaterialId | Aov::ObjectId => 1,
+            Aov::Normal | Aov::Albedo | Aov::Position => 3,
+        }
+    }
+
+    fn value(&self, film: &Film, x: usize, y: usize) -> [f64; 3] {
+        match self {
+            Aov::Depth => [film.depth(x, y).unwrap_or(f64::INFINITY), 0.0, 0.0],
+            Aov::Normal => {
+                let n = film.normal(x, y);
+                [n.x, n.y, n.z]
+            }
+            Aov::Albedo => {
+                let c = film.albedo(x, y);
+                [c.r, c.g, c.b]
+            }
+            Aov::Position => {
+                let p = film.position(x, y).unwrap_or(Vec3::zero());
+                [p.x, p.y, p.z]
+            }
+            Aov::MaterialId => [film.material_id(x, y) as f64, 0.0, 0.0],
+            Aov::ObjectId => [film.object_id(x, y) as f64, 0.0, 0.0],
+        }
+    }
+}
+
+/// Which AOVs to write, each to its own `<prefix>.<name>.pfm` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AovOutput {
+    pub prefix: PathBuf,
+    pub aovs: Vec<Aov>,
+}
+
+impl AovOutput {
+    pub fn new(prefix: impl Into<PathBuf>, aovs: Vec<Aov>) -> Self {
+        AovOutput {
+            prefix: prefix.into(),
+            aovs,
+        }
+    }
+
+    pub fn path(&self, aov: Aov) -> PathBuf {
+        let mut path = self.prefix.clone().into_os_string();
+        path.push(format!(".{}.pfm", aov.name()));
+        PathBuf::from(path)
+    }
+
+    pub fn write(&self, film: &Film) -> io::Result<()> {
+        for &aov in &self.aovs {
+            write_pfm(&self.path(aov), film, aov)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes one AOV as a Portable Float Map, which keeps full floating point
+/// values (IDs stay exact up to 2^24).
+fn write_pfm(path: &Path, film: &Film, aov: Aov) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let magic = if aov.channels() == 3 { "PF" } else { "Pf" };
+    // A negative scale marks little-endian data.
+    write!(out, "{magic}\n{} {}\n-1.0\n", film.width, film.height)?;
+
+    // PFM stores rows from the bottom of the image up.
+    for y in (0..film.height).rev() {
+        for x in 0..film.width {
+            let value = aov.value(film, x, y);
+            for &channel in &value[..aov.channels()] {
+                out.write_all(&(channel as f32).to_le_bytes())?;
+            }
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, film::SurfaceFeatures};
+
+    /// Header lines and little-endian samples of a PFM file.
+    fn read_pfm(path: &Path) -> (Vec<String>, Vec<f32>) {
+        let data = std::fs::read(path).unwrap();
+        let mut header = Vec::new();
+        let mut pos = 0;
+        while header.len() < 3 {
+            let end = pos + data[pos..].iter().position(|&b| b == b'\n').unwrap();
+            header.push(String::from_utf8(data[pos..end].to_vec()).unwrap());
+            pos = end + 1;
+        }
+        let samples = data[pos..]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        (header, samples)
+    }
+
+    #[test]
+    fn writes_pfm_bottom_row_first() {
+        let (width, height) = (2, 3);
+        let mut film = Film::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                // Distinct, exactly representable values per pixel.
+                let v = (y * width + x) as f64;
+                film.record_estimate(x, y, Color::new(0.0, 0.0, 0.0));
+                film.record_features(
+                    x,
+                    y,
+                    &SurfaceFeatures {
+                        albedo: Color::new(v / 8.0, 0.25, 0.5),
+                        normal: Vec3::new(0.0, 0.0, 1.0),
+                        depth: Some(v + 1.0),
+                        position: Vec3::new(v, -v, 2.0),
+                        material_id: 10 + v as u32,
+                        object_id: 20 + v as u32,
+                    },
+                );
+            }
+        }
+        let all = vec![
+            Aov::Depth,
+            Aov::Normal,
+            Aov::Albedo,
+            Aov::Position,
+            Aov::MaterialId,
+            Aov::ObjectId,
+        ];
+        let prefix = std::env::temp_dir().join(format!("ray_tracer_{}_aov", std::process::id()));
+        let output = AovOutput::new(prefix, all.clone());
+        output.write(&film).unwrap();
+
+        for aov in all {
+            let path = output.path(aov);
+            let (header, samples) = read_pfm(&path);
+            std::fs::remove_file(&path).unwrap();
+
+            let channels = aov.channels();
+            let magic = if channels == 3 { "PF" } else { "Pf" };
+            assert_eq!(header, [magic, "2 3", "-1.0"], "{aov:?}");
+            assert_eq!(samples.len(), width * height * channels, "{aov:?}");
+            // The file starts with pixel (0, 2), the fifth one written.
+            match aov {
+                Aov::Depth => assert_eq!(samples[0], 5.0),
+                Aov::Position => assert_eq!(samples[..3], [4.0, -4.0, 2.0]),
+                Aov::ObjectId => assert_eq!(samples[0], 24.0),
+                _ => {}
+            }
+            for y in 0..height {
+                for x in 0..width {
+                    let start = ((height - 1 - y) * width + x) * channels;
+                    let expected = aov.value(&film, x, y).map(|c| c as f32);
+                    assert_eq!(samples[start..start + channels], expected[..channels]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::{f64::consts::PI, io, path::Path};
+
+use crate::{
+    sampler::sample_unit_disk, texture::ImageTexture, util::degrees_to_radian, vec3::Vec3,
+};
+
+/// Shape of the lens opening, which out-of-focus highlights take on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Aperture {
+    Circle,
+    /// Regular polygon formed by `blades` straight diaphragm blades, at least
+    /// three, with corners on the unit circle, turned by `rotation` degrees.
+    Polygon {
+        blades: usize,
+        rotation: f64,
+    },
+    /// Opening drawn as an image covering [-1, 1]^2.
+    Image(ApertureImage),
+}
+
+impl Aperture {
+    /// Maps a uniform sample to a point of the opening, in the `z = 0` plane.
+    pub fn sample(&self, u: (f64, f64)) -> Vec3 {
+        match self {
+            Aperture::Circle => sample_unit_disk(u),
+            Aperture::Polygon { blades, rotation } => {
+                // Pick one of the triangles fanning out from the center, then a
+                // uniform point inside it.
+                let n = *blades as f64;
+                let k = (u.0 * n).floor().min(n - 1.0);
+                let u0 = u.0 * n - k;
+                let angle = degrees_to_radian(*rotation) + 2.0 * PI * k / n;
+                let a = Vec3::new(angle.cos(), angle.sin(), 0.0);
+                let next = angle + 2.0 * PI / n;
+                let b = Vec3::new(next.cos(), next.sin(), 0.0);
+                let s = u0.sqrt();
+                s * ((1.0 - u.1) * a + u.1 * b)
+            }
+            Aperture::Image(image) => image.sample(u),
+        }
+    }
+
+    /// Radius of the smallest circle around the center holding the opening.
+    pub fn bounding_radius(&self) -> f64 {
+        match self {
+            Aperture::Circle | Aperture::Polygon { .. } => 1.0,
+            Aperture::Image(_) => 2.0f64.sqrt(),
+        }
+    }
+}
+
+/// Aperture image tabulated for sampling, each pixel letting through light in
+/// proportion to its luminance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApertureImage {
+    width: usize,
+    height: usize,
+    // CDF over rows, then a CDF over the columns of each row.
+    row_cdf: Vec<f64>,
+    column_cdfs: Vec<f64>,
+}
+
+impl ApertureImage {
+    /// Fails if the image has no pixels.
+    pub fn new(image: &ImageTexture) -> io::Result<Self> {
+        let (width, height) = (image.width(), image.height());
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "aperture image is empty",
+            ));
+        }
+        let mut row_cdf = vec![0.0; height + 1];
+        let mut column_cdfs = vec![0.0; height * (width + 1)];
+        for y in 0..height {
+            let cdf = &mut column_cdfs[y * (width + 1)..(y + 1) * (width + 1)];
+            for x in 0..width {
+                cdf[x + 1] = cdf[x] + image.pixel(x, y).luminance().max(0.0);
+            }
+            row_cdf[y + 1] = row_cdf[y] + cdf[width];
+            normalize(cdf);
+        }
+        normalize(&mut row_cdf);
+
+        Ok(ApertureImage {
+            width,
+            height,
+            row_cdf,
+            column_cdfs,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        ApertureImage::new(&ImageTexture::load(path)?)
+    }
+
+    fn sample(&self, u: (f64, f64)) -> Vec3 {
+        let y = sample_cdf(&self.row_cdf, u.1);
+        let row = (y as usize).min(self.height - 1);
+        let x = sample_cdf(
+            &self.column_cdfs[row * (self.width + 1)..(row + 1) * (self.width + 1)],
+            u.0,
+        );
+        // The top of the image is the top of the opening.
+        Vec3::new(
+            2.0 * x / self.width as f64 - 1.0,
+            1.0 - 2.0 * y / self.height as f64,
+            0.0,
+        )
+    }
+}
+
+/// Scales a CDF to end at one, or makes it uniform if it is all zero.
+fn normalize(cdf: &mut [f64]) {
+    let n = cdf.len() - 1;
+    let total = cdf[n];
+    for (i, c) in cdf.iter_mut().enumerate() {
+        *c = if total > 0.0 {
+            *c / total
+        } else {
+            i as f64 / n as f64
+        };
+    }
+}
+
+/// Continuous position, in bins, that `u` maps to under a tabulated CDF.
+fn sample_cdf(cdf: &[f64], u: f64) -> f64 {
+    let bins = cdf.len() - 1;
+    let b = cdf.partition_point(|&c| c <= u).clamp(1, bins) - 1;
+    let width = cdf[b + 1] - cdf[b];
+    let t = if width > 0.0 {
+        (u - cdf[b]) / width
+    } else {
+        0.5
+    };
+    b as f64 + t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_empty_images() {
+        assert!(ApertureImage::new(&ImageTexture::new(0, 0, Vec::new())).is_err());
+        assert!(ApertureImage::new(&ImageTexture::new(3, 0, Vec::new())).is_err());
+    }
+}
@@ -1,27 +1,358 @@
+use std::{
+    borrow::Cow,
+    error::Error,
+    f64::consts::PI,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
 use crate::{
-    color::{Color, write_color},
-    hittable::Hittable,
+    animation::{self, CameraAnimation, FrameSequence},
+    aov::AovOutput,
+    aperture::Aperture,
+    checkpoint::{self, Checkpoint, CheckpointHeader},
+    color::{Color, write_ppm},
+    denoise::Denoiser,
+    film::{Film, SurfaceFeatures},
+    filter::{Filter, FilterMode, FilterSampler},
+    hittable::{HitRecord, Hittable},
     interval::Interval,
+    lens::LensSystem,
     ray::Ray,
-    util::{degrees_to_radian, random_f64},
+    sampler::{Sampler, SamplerType, sample_unit_disk},
+    tonemap::ToneMapper,
+    util::{degrees_to_radian, hash_u64s},
     vec3::{Point3, Vec3},
 };
 
+/// Keeps sampling a pixel until the standard error of its luminance, relative
+/// to its mean, falls below `error_threshold`, taking between `min_samples`
+/// and `max_samples` samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptiveSampling {
+    pub min_samples: usize,
+    pub max_samples: usize,
+    pub error_threshold: f64,
+    /// Optional PPM file showing how many samples each pixel took.
+    pub heatmap_path: Option<PathBuf>,
+}
+
+impl AdaptiveSampling {
+    pub fn new(min_samples: usize, max_samples: usize, error_threshold: f64) -> Self {
+        AdaptiveSampling {
+            min_samples,
+            max_samples,
+            error_threshold,
+            heatmap_path: None,
+        }
+    }
+}
+
+/// Renders the whole image in passes of `samples_per_pass` samples per pixel,
+/// optionally stopping after the pass in which `time_budget` runs out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progressive {
+    pub samples_per_pass: usize,
+    pub time_budget: Option<Duration>,
+}
+
+impl Progressive {
+    pub fn new(samples_per_pass: usize) -> Self {
+        Progressive {
+            samples_per_pass,
+            time_budget: None,
+        }
+    }
+}
+
+/// Settings of a real camera. The field of view follows from the focal
+/// length and sensor height, the depth of field from the f-number, and the
+/// exposure from the f-number, shutter time and ISO, taking scene radiance
+/// to be in cd/m^2.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhysicalCamera {
+    /// Focal length in millimeters.
+    pub focal_length: f64,
+    /// Sensor size in millimeters. The image must share its aspect ratio.
+    pub sensor_width: f64,
+    pub sensor_height: f64,
+    pub f_number: f64,
+    /// Shutter time in seconds.
+    pub shutter_time: f64,
+    pub iso: f64,
+    /// Size of a millimeter in scene units.
+    pub scene_units_per_mm: f64,
+}
+
+impl PhysicalCamera {
+    /// A full frame (36 x 24 mm) camera at 1/125 s and ISO 100.
+    pub fn new(focal_length: f64, f_number: f64) -> Self {
+        PhysicalCamera {
+            focal_length,
+            sensor_width: 36.0,
+            sensor_height: 24.0,
+            f_number,
+            shutter_time: 1.0 / 125.0,
+            iso: 100.0,
+            scene_units_per_mm: 0.001,
+        }
+    }
+
+    /// Vertical field of view in degrees.
+    pub fn vfov(&self) -> f64 {
+        2.0 * (self.sensor_height / (2.0 * self.focal_length))
+            .atan()
+            .to_degrees()
+    }
+
+    /// Cone angle of the aperture, `focal_length / f_number` wide, seen from
+    /// `focus_dist` away.
+    pub fn defocus_angle(&self, focus_dist: f64) -> f64 {
+        let radius = 0.5 * self.focal_length / self.f_number * self.scene_units_per_mm;
+        2.0 * (radius / focus_dist).atan().to_degrees()
+    }
+
+    /// Exposure value at ISO 100.
+    pub fn ev100(&self) -> f64 {
+        (self.f_number * self.f_number / self.shutter_time * 100.0 / self.iso).log2()
+    }
+
+    /// Exposure in stops that maps the luminance saturating the sensor to
+    /// one, following the standard saturation-based speed model.
+    pub fn exposure(&self) -> f64 {
+        -self.ev100() - 1.2f64.log2()
+    }
+
+    fn is_valid(&self) -> bool {
+        [
+            self.focal_length,
+            self.sensor_width,
+            self.sensor_height,
+            self.f_number,
+            self.shutter_time,
+            self.iso,
+            self.scene_units_per_mm,
+        ]
+        .iter()
+        .all(|&value| value.is_finite() && value > 0.0)
+    }
+}
+
+/// Where the camera measures the distance to focus at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AutoFocus {
+    /// The middle of the image.
+    Center,
+    /// Pixel (x, y) of the written image at full resolution, from its
+    /// upper-left corner. Stereo cameras measure in the view of the eye the
+    /// pixel belongs to.
+    Pixel { x: usize, y: usize },
+}
+
+/// How directions in the scene map to positions on the image.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Projection {
+    /// Pinhole or thin lens camera with a vertical field of view of `vfov`.
+    Perspective,
+    /// Parallel rays covering `view_height` world units vertically.
+    Orthographic { view_height: f64 },
+    /// Circular fisheye covering `fov` degrees across the largest circle that
+    /// fits in the image. Pixels outside the circle are black.
+    Fisheye { mapping: FisheyeMapping, fov: f64 },
+    /// Full 360 by 180 degree latitude-longitude panorama, centered on the
+    /// view direction. Use an aspect ratio of 2.
+    Equirectangular,
+    /// Rays traced through a lens prescription, focused at `focus_dist`. Its
+    /// field of view follows from the lens and film size, and its depth of
+    /// field from the aperture stop, replacing `vfov` and `defocus_angle`.
+    /// Rays blocked inside the lens carry no light.
+    Realistic(LensSystem),
+}
+
+/// Relation between the angle from the view direction and the distance from
+/// the center of a fisheye image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FisheyeMapping {
+    /// Distance proportional to the angle.
+    Equidistant,
+    /// Distance proportional to `sin(angle / 2)`, preserving solid angles.
+    Equisolid,
+}
+
+/// Renders a view for each eye and writes them together as one image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stereo {
+    pub interocular_distance: f64,
+    /// Distance at which the two views line up, with zero parallax. Infinite
+    /// for parallel eyes. Orthographic cameras ignore it, as their eyes always
+    /// look along parallel rays, and so do realistic lenses, whose eyes are
+    /// two copies of the lens shifted sideways.
+    pub convergence_distance: f64,
+    pub layout: StereoLayout,
+}
+
+impl Stereo {
+    pub fn new(interocular_distance: f64, convergence_distance: f64) -> Self {
+        Stereo {
+            interocular_distance,
+            convergence_distance,
+            layout: StereoLayout::SideBySide,
+        }
+    }
+}
+
+/// Arrangement of the eyes' views in the written image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoLayout {
+    /// Left eye on the left, right eye on the right.
+    SideBySide,
+    /// Left eye on top, right eye at the bottom.
+    TopBottom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Eye {
+    Center,
+    Left,
+    Right,
+}
+
+/// Region of the image to render, as fractions of its width and height
+/// measured from the upper-left corner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CropWindow {
+    pub x_min: f64,
+    pub x_max: f64,
+    pub y_min: f64,
+    pub y_max: f64,
+}
+
+impl CropWindow {
+    pub fn new(x_min: f64, x_max: f64, y_min: f64, y_max: f64) -> Self {
+        CropWindow {
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+        }
+    }
+}
+
+/// Rectangle of pixels in the full image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PixelBounds {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl PixelBounds {
+    /// Pixels in both rectangles, if any.
+    fn intersection(&self, other: &PixelBounds) -> Option<PixelBounds> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let x_end = (self.x + self.width).min(other.x + other.width);
+        let y_end = (self.y + self.height).min(other.y + other.height);
+        (x < x_end && y < y_end).then_some(PixelBounds {
+            x,
+            y,
+            width: x_end - x,
+            height: y_end - y,
+        })
+    }
+}
+
 pub struct Camera {
     pub aspect_ratio: f64,
     pub image_width: usize,
+    /// Multiplies the image resolution without changing the view, for quick
+    /// previews.
+    pub resolution_scale: f64,
+    /// When set, only this part of the image is rendered and written. Its
+    /// pixels match the same pixels of a full render.
+    pub crop_window: Option<CropWindow>,
     pub sample_per_pixel: usize,
     pub max_depth: usize,
+    pub projection: Projection,
+    /// When set, renders a view for each eye. Equirectangular panoramas use
+    /// omni-directional stereo, with the eyes circling the camera center.
+    pub stereo: Option<Stereo>,
     pub vfov: f64,
     pub look_from: Point3,
     pub look_at: Point3,
     pub vup: Vec3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    /// When set, `vfov` and `defocus_angle` are ignored and derived from these
+    /// camera settings instead, and their exposure is added to `exposure`,
+    /// which then acts as exposure compensation.
+    pub physical: Option<PhysicalCamera>,
+    /// When set, `focus_dist` is replaced before rendering so that the plane
+    /// of focus, tilted or not, passes through the surface seen at the chosen
+    /// point. It is kept when nothing is there, or when a realistic lens cannot
+    /// focus on it.
+    pub autofocus: Option<AutoFocus>,
+    /// Lens shift in image widths and heights, moving the view sideways and
+    /// up without turning the camera, so verticals stay parallel. Only
+    /// perspective and orthographic projections support shift and tilt.
+    pub shift_x: f64,
+    pub shift_y: f64,
+    /// Rotation of the plane of focus about the camera's horizontal
+    /// (`tilt_x`) and vertical (`tilt_y`) axes, in degrees. A positive
+    /// `tilt_x` brings the bottom of the plane closer, along the ground.
+    /// Tilts must stay strictly between -90 and 90 degrees.
+    pub tilt_x: f64,
+    pub tilt_y: f64,
+    /// Shape of the lens opening used for depth of field.
+    pub aperture: Aperture,
+    /// How far the shadow of the lens barrel moves across the aperture, in
+    /// aperture radii, at the image corners. It darkens the corners and clips
+    /// out-of-focus highlights there into cat's eyes. 0 disables it.
+    pub optical_vignetting: f64,
+    pub sampler: SamplerType,
+    /// Seed for every random number drawn while rendering. Each sample's
+    /// numbers are derived from the seed, the pixel and the sample index, so
+    /// renders are reproducible bit for bit.
+    pub seed: u64,
+    /// Scene time of the image, carried by every ray to place animated
+    /// objects.
+    pub time: f64,
+    pub filter: Filter,
+    pub filter_mode: FilterMode,
+    /// When set, replaces the fixed `sample_per_pixel` with per-pixel
+    /// adaptive sampling.
+    pub adaptive: Option<AdaptiveSampling>,
+    /// When set, renders the image in passes and refreshes `output_path`
+    /// after each one.
+    pub progressive: Option<Progressive>,
+    /// When set, the render state is saved periodically and can be resumed.
+    pub checkpoint: Option<Checkpoint>,
+    /// When set, the finished image is denoised using the albedo, normal and
+    /// depth buffers gathered during rendering.
+    pub denoise: Option<Denoiser>,
+    /// Exposure adjustment in stops applied before tone mapping.
+    pub exposure: f64,
+    pub tone_mapper: ToneMapper,
+    /// Extra image layers written alongside the final image.
+    pub aov_output: Option<AovOutput>,
+    /// File the final image is written to, or standard output if unset.
+    pub output_path: Option<PathBuf>,
 
     //private
+    // Size of one eye's view.
+    scaled_width: usize,
     image_height: usize,
-    pixel_samples_scale: f64,
+    crop: PixelBounds,
+    // The crop plus the pixels whose splats reach into it.
+    sample_bounds: PixelBounds,
+    focused_lens: Option<LensSystem>,
+    // `vfov` and `defocus_angle`, or their values for the physical camera.
+    render_vfov: f64,
+    render_defocus_angle: f64,
     center: Point3,
     pixel00_loc: Point3,
     pixel_delta_u: Vec3,
@@ -33,6 +364,13 @@ pub struct Camera {
     w: Vec3,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    focus_plane_normal: Vec3,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Camera {
@@ -40,15 +378,55 @@ impl Camera {
         Camera {
             aspect_ratio: 1.0,
             image_width: 100,
+            resolution_scale: 1.0,
+            crop_window: None,
+            scaled_width: 0,
             image_height: 0,
+            crop: PixelBounds {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            },
+            sample_bounds: PixelBounds {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            },
+            focused_lens: None,
+            render_vfov: 0.0,
+            render_defocus_angle: 0.0,
             max_depth: 10,
+            projection: Projection::Perspective,
+            stereo: None,
             vfov: 90.0,
             look_from: Point3::zero(),
             look_at: Point3::new(0.0, 0.0, -1.0),
             vup: Vec3::new(0.0, 1.0, 0.0),
             defocus_angle: 0.0,
             focus_dist: 10.0,
-            pixel_samples_scale: 0.0,
+            physical: None,
+            autofocus: None,
+            shift_x: 0.0,
+            shift_y: 0.0,
+            tilt_x: 0.0,
+            tilt_y: 0.0,
+            aperture: Aperture::Circle,
+            optical_vignetting: 0.0,
+            sampler: SamplerType::Independent,
+            seed: 0,
+            time: 0.0,
+            filter: Filter::Box { radius: 0.5 },
+            filter_mode: FilterMode::ImportanceSampling,
+            adaptive: None,
+            progressive: None,
+            checkpoint: None,
+            denoise: None,
+            aov_output: None,
+            exposure: 0.0,
+            tone_mapper: ToneMapper::Clamp,
+            output_path: None,
             sample_per_pixel: 10,
             center: Point3::zero(),
             pixel00_loc: Point3::zero(),
@@ -59,46 +437,637 @@ impl Camera {
             w: Vec3::zero(),
             defocus_disk_u: Vec3::zero(),
             defocus_disk_v: Vec3::zero(),
+            focus_plane_normal: Vec3::zero(),
         }
     }
-    pub fn render(&mut self, world: &impl Hittable) {
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder::new()
+    }
+
+    /// Checks that the configuration describes a renderable image.
+    pub fn validate(&self) -> Result<(), CameraError> {
+        if let Some(physical) = &self.physical
+            && !physical.is_valid()
+        {
+            return Err(CameraError::InvalidPhysicalCamera);
+        }
+        if !(self.aspect_ratio.is_finite() && self.aspect_ratio > 0.0) {
+            return Err(CameraError::InvalidAspectRatio(self.aspect_ratio));
+        }
+        if let Some(physical) = &self.physical {
+            let sensor = physical.sensor_width / physical.sensor_height;
+            if (sensor - self.aspect_ratio).abs() > 1e-3 * self.aspect_ratio {
+                return Err(CameraError::SensorAspectMismatch {
+                    sensor,
+                    image: self.aspect_ratio,
+                });
+            }
+        }
+        if !(self.resolution_scale.is_finite() && self.resolution_scale > 0.0) {
+            return Err(CameraError::InvalidResolutionScale(self.resolution_scale));
+        }
+        let width = (self.image_width as f64 * self.resolution_scale).round() as usize;
+        let height = (width as f64 / self.aspect_ratio) as usize;
+        if width == 0 || height == 0 {
+            return Err(CameraError::EmptyImage { width, height });
+        }
+
+        let placement = [self.look_from, self.look_at, self.vup];
+        if !placement
+            .iter()
+            .all(|v| [v.x, v.y, v.z].iter().all(|c| c.is_finite()))
+        {
+            return Err(CameraError::NonFinitePlacement);
+        }
+        let view = self.look_from - self.look_at;
+        if view.near_zero() {
+            return Err(CameraError::LookFromEqualsLookAt);
+        }
+        if self.vup.near_zero()
+            || Vec3::cross(Vec3::unit_vector(self.vup), Vec3::unit_vector(view)).near_zero()
+        {
+            return Err(CameraError::VupParallelToView);
+        }
+
+        match self.projection {
+            Projection::Perspective
+                if self.physical.is_none() && !(self.vfov > 0.0 && self.vfov < 180.0) =>
+            {
+                return Err(CameraError::InvalidFieldOfView(self.vfov));
+            }
+            Projection::Orthographic { view_height }
+                if !(view_height.is_finite() && view_height > 0.0) =>
+            {
+                return Err(CameraError::InvalidViewHeight(view_height));
+            }
+            Projection::Fisheye { fov, .. } if !(fov > 0.0 && fov <= 360.0) => {
+                return Err(CameraError::InvalidFieldOfView(fov));
+            }
+            _ => {}
+        }
+        if !(self.focus_dist.is_finite() && self.focus_dist > 0.0) {
+            return Err(CameraError::InvalidFocusDistance(self.focus_dist));
+        }
+        if let Projection::Realistic(lens) = &self.projection {
+            if !lens.is_valid() {
+                return Err(CameraError::InvalidLens);
+            }
+            if lens.focused(self.focus_dist).is_none() {
+                return Err(CameraError::LensCannotFocus(self.focus_dist));
+            }
+        }
+        if self.physical.is_none() && !(self.defocus_angle >= 0.0 && self.defocus_angle < 180.0) {
+            return Err(CameraError::InvalidDefocusAngle(self.defocus_angle));
+        }
+        if let Aperture::Polygon { blades, .. } = self.aperture
+            && blades < 3
+        {
+            return Err(CameraError::InvalidApertureBlades(blades));
+        }
+        for tilt in [self.tilt_x, self.tilt_y] {
+            if !(tilt > -90.0 && tilt < 90.0) {
+                return Err(CameraError::InvalidTilt(tilt));
+            }
+        }
+        if matches!(
+            self.projection,
+            Projection::Fisheye { .. } | Projection::Equirectangular | Projection::Realistic(_)
+        ) && (self.shift_x != 0.0
+            || self.shift_y != 0.0
+            || self.tilt_x != 0.0
+            || self.tilt_y != 0.0)
+        {
+            return Err(CameraError::UnsupportedLensMovement);
+        }
+        if let Some(stereo) = &self.stereo
+            && !(stereo.interocular_distance.is_finite()
+                && stereo.interocular_distance > 0.0
+                && stereo.convergence_distance > 0.0)
+        {
+            return Err(CameraError::InvalidStereo);
+        }
+
+        let samples_valid = match &self.adaptive {
+            Some(adaptive) => {
+                adaptive.max_samples > 0 && adaptive.min_samples <= adaptive.max_samples
+            }
+            None => self.sample_per_pixel > 0,
+        };
+        if !samples_valid {
+            return Err(CameraError::InvalidSampleCount);
+        }
+        if let Some(progressive) = &self.progressive
+            && progressive.samples_per_pass == 0
+        {
+            return Err(CameraError::InvalidProgressivePass);
+        }
+        let radius = self.filter.radius();
+        if !(radius.is_finite() && radius > 0.0) {
+            return Err(CameraError::InvalidFilterRadius(radius));
+        }
+        match self.filter {
+            Filter::Gaussian { sigma, .. } if !(sigma.is_finite() && sigma > 0.0) => {
+                return Err(CameraError::InvalidGaussianSigma(sigma));
+            }
+            Filter::Lanczos { tau, .. } if !(tau.is_finite() && tau > 0.0) => {
+                return Err(CameraError::InvalidLanczosTau(tau));
+            }
+            Filter::Mitchell { b, c, .. } if !(b.is_finite() && c.is_finite()) => {
+                return Err(CameraError::InvalidMitchellParameters { b, c });
+            }
+            _ => {}
+        }
+        if !self.time.is_finite() {
+            return Err(CameraError::InvalidTime(self.time));
+        }
+        if !self.exposure.is_finite() {
+            return Err(CameraError::InvalidExposure(self.exposure));
+        }
+        if let Some(denoiser) = &self.denoise
+            && !denoiser.is_valid()
+        {
+            return Err(CameraError::InvalidDenoiser);
+        }
+        if let Some(window) = &self.crop_window
+            && !(0.0 <= window.x_min
+                && window.x_min < window.x_max
+                && window.x_max <= 1.0
+                && 0.0 <= window.y_min
+                && window.y_min < window.y_max
+                && window.y_max <= 1.0)
+        {
+            return Err(CameraError::InvalidCropWindow);
+        }
+        if let Some(AutoFocus::Pixel { x, y }) = self.autofocus {
+            let full_height = (self.image_width as f64 / self.aspect_ratio) as usize;
+            let (columns, rows) = self.layout_size(self.image_width, full_height);
+            if x >= columns || y >= rows {
+                return Err(CameraError::InvalidAutoFocusPixel);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn render(&mut self, world: &impl Hittable) -> io::Result<()> {
+        let film = self.render_film(world)?;
+
+        let image = self.develop(&film);
+        let film = self.crop_film(&film);
+        if let Some(AdaptiveSampling {
+            heatmap_path: Some(path),
+            ..
+        }) = &self.adaptive
+        {
+            let max_samples = self.max_samples();
+            film.write_sample_heatmap(&mut BufWriter::new(File::create(path)?), max_samples)?;
+        }
+        if let Some(aov_output) = &self.aov_output {
+            aov_output.write(&film)?;
+        }
+        match &self.output_path {
+            Some(path) => write_ppm(
+                &mut BufWriter::new(File::create(path)?),
+                film.width,
+                film.height,
+                &image,
+            ),
+            None => write_ppm(&mut io::stdout(), film.width, film.height, &image),
+        }
+    }
+
+    /// Takes the samples of the render, covering `sample_bounds`, resuming
+    /// from a checkpoint if asked to.
+    fn render_film(&mut self, world: &impl Hittable) -> io::Result<Film> {
+        self.validate()?;
         Self::initialize(self);
-        let mut out = std::io::stdout();
+        if let Some(autofocus) = self.autofocus
+            && let Some(focus_dist) = self.autofocus_distance(world, autofocus)
+        {
+            self.focus_dist = focus_dist;
+            Self::initialize(self);
+        }
+        let max_samples = self.max_samples();
+        let mut sampler = self.sampler.build(max_samples, self.seed);
+        let filter_sampler = FilterSampler::new(self.filter);
+        let start_time = Instant::now();
+        let mut last_checkpoint = Instant::now();
+
+        let bounds = self.sample_bounds;
+        let mut header = CheckpointHeader {
+            x_offset: bounds.x,
+            y_offset: bounds.y,
+            width: bounds.width,
+            height: bounds.height,
+            seed: self.seed,
+            settings: self.settings_hash(),
+            max_samples,
+            samples_done: 0,
+        };
+        let mut film = match &self.checkpoint {
+            Some(checkpoint) if checkpoint.resume && checkpoint.path.exists() => {
+                let (saved, film) = checkpoint::load(&checkpoint.path)?;
+                if saved.x_offset != header.x_offset
+                    || saved.y_offset != header.y_offset
+                    || saved.width != header.width
+                    || saved.height != header.height
+                    || saved.seed != header.seed
+                    || saved.settings != header.settings
+                    || saved.max_samples != header.max_samples
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "checkpoint was written by a render with different settings",
+                    ));
+                }
+                eprintln!("Resuming from {}", checkpoint.path.display());
+                header.samples_done = saved.samples_done;
+                film
+            }
+            _ => Film::new(bounds.width, bounds.height),
+        };
+
+        let samples_per_pass = match &self.progressive {
+            Some(progressive) => progressive.samples_per_pass,
+            None => max_samples,
+        };
+        while header.samples_done < max_samples {
+            let samples_done = header.samples_done;
+            let pass_end = (samples_done + samples_per_pass).min(max_samples);
+
+            for j in 0..bounds.height {
+                eprint!(
+                    "\rSamples {}-{} of {}, scanlines remaining {}",
+                    samples_done + 1,
+                    pass_end,
+                    max_samples,
+                    bounds.height - j
+                );
+                for i in 0..bounds.width {
+                    // Pixels already sampled in this pass were restored from
+                    // a checkpoint.
+                    let first_sample = (film.sample_count(i, j) as usize).max(samples_done);
+                    for sample in first_sample..pass_end {
+                        if self.is_converged(&film, i, j) {
+                            break;
+                        }
+                        self.sample_pixel(
+                            i,
+                            j,
+                            sample,
+                            world,
+                            &mut *sampler,
+                            &filter_sampler,
+                            &mut film,
+                        );
+                    }
+                }
+
+                if let Some(checkpoint) = &self.checkpoint
+                    && last_checkpoint.elapsed() >= checkpoint.interval
+                {
+                    checkpoint::save(&checkpoint.path, &header, &film)?;
+                    last_checkpoint = Instant::now();
+                }
+            }
+            header.samples_done = pass_end;
+            if let Some(checkpoint) = &self.checkpoint {
+                checkpoint::save(&checkpoint.path, &header, &film)?;
+                last_checkpoint = Instant::now();
+            }
+
+            if let Some(progressive) = &self.progressive {
+                if header.samples_done < max_samples {
+                    self.write_snapshot(&film)?;
+                }
+                if progressive
+                    .time_budget
+                    .is_some_and(|budget| start_time.elapsed() >= budget)
+                {
+                    break;
+                }
+            }
+        }
+        eprint!("\rDone                                                  \n");
+        Ok(film)
+    }
+
+    /// Hash of the settings that determine the samples taken, which a
+    /// checkpoint must match to be resumed. Progressive passes, and what
+    /// happens to the film once it is sampled, do not matter.
+    fn settings_hash(&self) -> u64 {
+        let adaptive = self.adaptive.as_ref().map(|adaptive| {
+            (
+                adaptive.min_samples,
+                adaptive.max_samples,
+                adaptive.error_threshold,
+            )
+        });
+        // Debug output prints floats exactly, so it tells any two settings
+        // apart.
+        let settings = format!(
+            "{:?}",
+            (
+                (self.aspect_ratio, self.image_width, self.resolution_scale),
+                (&self.crop_window, self.sample_per_pixel, self.max_depth),
+                (&self.projection, &self.stereo, self.vfov),
+                (self.look_from, self.look_at, self.vup),
+                (self.defocus_angle, self.focus_dist, &self.physical),
+                (self.autofocus, self.shift_x, self.shift_y),
+                (self.tilt_x, self.tilt_y, &self.aperture),
+                (self.optical_vignetting, self.sampler, self.time),
+                (self.filter, self.filter_mode, adaptive),
+            )
+        );
+        hash_u64s(&settings.bytes().map(u64::from).collect::<Vec<_>>())
+    }
+
+    /// The part of `film` inside the crop window, without the margin rendered
+    /// around it.
+    fn crop_film<'a>(&self, film: &'a Film) -> Cow<'a, Film> {
+        if self.crop == self.sample_bounds {
+            return Cow::Borrowed(film);
+        }
+        Cow::Owned(film.crop(
+            self.crop.x - self.sample_bounds.x,
+            self.crop.y - self.sample_bounds.y,
+            self.crop.width,
+            self.crop.height,
+        ))
+    }
+
+    /// Renders the frames of `sequence`, posing the camera with `animation`
+    /// for each. Every frame gets its own seed, and its own numbered
+    /// checkpoint and AOV files. The camera is left as it was before.
+    pub fn render_sequence(
+        &mut self,
+        world: &impl Hittable,
+        animation: &CameraAnimation,
+        sequence: &FrameSequence,
+    ) -> io::Result<()> {
+        let pose = (
+            self.look_from,
+            self.look_at,
+            self.vfov,
+            self.focus_dist,
+            self.time,
+        );
+        let seed = self.seed;
+        let output_path = self.output_path.take();
+        let aov_output = self.aov_output.clone();
+        let checkpoint = self.checkpoint.clone();
 
-        println!("P3\n {} {}\n255", self.image_width, self.image_height);
+        let result = sequence.frames.clone().try_for_each(|frame| {
+            eprintln!("Frame {frame}");
+            animation.apply(self, sequence.time(frame));
+            self.seed = hash_u64s(&[seed, frame as u64]);
+            self.output_path = Some(sequence.path(frame));
+            self.aov_output = aov_output.as_ref().map(|aov| AovOutput {
+                prefix: animation::numbered(&aov.prefix, frame),
+                aovs: aov.aovs.clone(),
+            });
+            self.checkpoint = checkpoint.as_ref().map(|checkpoint| Checkpoint {
+                path: animation::numbered(&checkpoint.path, frame),
+                ..checkpoint.clone()
+            });
+            self.render(world)
+        });
 
-        for j in 0..self.image_height {
-            eprint!("\rScanlines remaining {}", self.image_height - j);
-            for i in 0..self.image_width {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+        (
+            self.look_from,
+            self.look_at,
+            self.vfov,
+            self.focus_dist,
+            self.time,
+        ) = pose;
+        self.seed = seed;
+        self.output_path = output_path;
+        self.aov_output = aov_output;
+        self.checkpoint = checkpoint;
+        result
+    }
 
-                for _sample in 0..self.sample_per_pixel {
-                    let r = self.get_ray(i, j);
-                    pixel_color = pixel_color + self.ray_color(&r, self.max_depth, world);
+    /// Turns the film, covering `sample_bounds`, into the final image of the
+    /// crop window: denoising, cropping, then exposure and tone mapping. The
+    /// whole film is denoised so that pixels at the edge of the crop see the
+    /// same neighbourhood as in a full render, one eye's view at a time so
+    /// the views do not bleed into each other.
+    fn develop(&self, film: &Film) -> Vec<Color> {
+        let mut image = film.resolve();
+        if let Some(denoiser) = &self.denoise {
+            let bounds = self.sample_bounds;
+            let eyes: &[Eye] = match self.stereo {
+                Some(_) => &[Eye::Left, Eye::Right],
+                None => &[Eye::Center],
+            };
+            for &eye in eyes {
+                let Some(view) = self.eye_bounds(eye).intersection(&bounds) else {
+                    continue;
+                };
+                let (x, y) = (view.x - bounds.x, view.y - bounds.y);
+                let view_film = film.crop(x, y, view.width, view.height);
+                let denoised = denoiser.apply(&view_film, &view_film.resolve());
+                for (row, pixels) in denoised.chunks_exact(view.width).enumerate() {
+                    let start = (y + row) * bounds.width + x;
+                    image[start..start + view.width].copy_from_slice(pixels);
                 }
-                write_color(&mut out, self.pixel_samples_scale * pixel_color);
             }
         }
-        eprint!("\rDone                               \n");
+
+        let stops = self.exposure + self.physical.as_ref().map_or(0.0, |p| p.exposure());
+        let scale = stops.exp2();
+        let (crop, bounds) = (self.crop, self.sample_bounds);
+        (0..crop.height)
+            .flat_map(|row| {
+                let start = (crop.y - bounds.y + row) * bounds.width + crop.x - bounds.x;
+                start..start + crop.width
+            })
+            .map(|index| self.tone_mapper.apply(scale * image[index]))
+            .collect()
+    }
+
+    /// Whether adaptive sampling has already taken enough samples in pixel
+    /// (i, j).
+    fn is_converged(&self, film: &Film, i: usize, j: usize) -> bool {
+        match &self.adaptive {
+            Some(adaptive) => {
+                film.sample_count(i, j) as usize >= adaptive.min_samples
+                    && film.relative_error(i, j) < adaptive.error_threshold
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the image at `output_path` with the current state of the film.
+    /// The image is written next to it first, so viewers never see a partial
+    /// file.
+    fn write_snapshot(&self, film: &Film) -> io::Result<()> {
+        let Some(path) = &self.output_path else {
+            return Ok(());
+        };
+        let mut partial = path.clone().into_os_string();
+        partial.push(".partial");
+        let image = self.develop(film);
+        let film = self.crop_film(film);
+        write_ppm(
+            &mut BufWriter::new(File::create(&partial)?),
+            film.width,
+            film.height,
+            &image,
+        )?;
+        fs::rename(&partial, path)
+    }
+
+    /// Upper bound on the samples taken in any pixel.
+    fn max_samples(&self) -> usize {
+        match &self.adaptive {
+            Some(adaptive) => adaptive.max_samples,
+            None => self.sample_per_pixel,
+        }
+    }
+
+    /// Traces sample number `sample` of film pixel (i, j) and records it in
+    /// `film`.
+    #[allow(clippy::too_many_arguments)]
+    fn sample_pixel(
+        &self,
+        i: usize,
+        j: usize,
+        sample: usize,
+        world: &impl Hittable,
+        sampler: &mut dyn Sampler,
+        filter_sampler: &FilterSampler,
+        film: &mut Film,
+    ) {
+        // Sampling and projection work in full image pixels, so a region
+        // renders exactly as it would in the full image.
+        let pixel = (i + self.sample_bounds.x, j + self.sample_bounds.y);
+        sampler.start_pixel_sample(pixel, sample);
+        let (offset, weight) = match self.filter_mode {
+            FilterMode::ImportanceSampling => filter_sampler.sample(sampler.get_2d()),
+            FilterMode::Splatting => (self.sample_square(sampler), 1.0),
+        };
+        let (eye, view_i, view_j) = self.eye_pixel(pixel.0, pixel.1);
+        let (color, features) = match self.get_ray(eye, view_i, view_j, offset, sampler) {
+            Some(r) => {
+                self.primary_ray_color(&r.with_time(self.time).with_seed(self.seed), world, sampler)
+            }
+            None => (Color::new(0.0, 0.0, 0.0), SurfaceFeatures::background()),
+        };
+
+        film.record_estimate(i, j, color);
+        film.record_features(i, j, &features);
+        match self.filter_mode {
+            FilterMode::ImportanceSampling => film.add_estimate(i, j, color, weight),
+            FilterMode::Splatting => {
+                // Keep each eye's samples out of the other eye's view.
+                let view = self.eye_bounds(eye);
+                let (x, y) = (self.sample_bounds.x, self.sample_bounds.y);
+                film.splat_within(
+                    (i, j),
+                    offset,
+                    color,
+                    &self.filter,
+                    view.x.saturating_sub(x)..(view.x + view.width).saturating_sub(x),
+                    view.y.saturating_sub(y)..(view.y + view.height).saturating_sub(y),
+                );
+            }
+        }
+    }
+
+    /// Size of the written image, which holds every eye's view.
+    fn output_size(&self) -> (usize, usize) {
+        self.layout_size(self.scaled_width, self.image_height)
+    }
+
+    /// Size of the written image for views of `width` by `height` pixels.
+    fn layout_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self.stereo.as_ref().map(|stereo| stereo.layout) {
+            None => (width, height),
+            Some(StereoLayout::SideBySide) => (2 * width, height),
+            Some(StereoLayout::TopBottom) => (width, 2 * height),
+        }
+    }
+
+    /// Pixels of the written image showing the view of `eye`.
+    fn eye_bounds(&self, eye: Eye) -> PixelBounds {
+        let (x, y) = match (eye, self.stereo.as_ref().map(|stereo| stereo.layout)) {
+            (Eye::Right, Some(StereoLayout::SideBySide)) => (self.scaled_width, 0),
+            (Eye::Right, Some(StereoLayout::TopBottom)) => (0, self.image_height),
+            _ => (0, 0),
+        };
+        PixelBounds {
+            x,
+            y,
+            width: self.scaled_width,
+            height: self.image_height,
+        }
+    }
+
+    /// The eye whose view contains pixel (i, j) of the written image, and the
+    /// pixel's position in that view.
+    fn eye_pixel(&self, i: usize, j: usize) -> (Eye, usize, usize) {
+        if self.stereo.is_none() {
+            return (Eye::Center, i, j);
+        }
+        let right = self.eye_bounds(Eye::Right);
+        if i >= right.x && j >= right.y {
+            (Eye::Right, i - right.x, j - right.y)
+        } else {
+            (Eye::Left, i, j)
+        }
+    }
+
+    /// Signed distance of the eye from the camera center, to the right.
+    fn eye_shift(&self, eye: Eye) -> f64 {
+        let Some(stereo) = &self.stereo else {
+            return 0.0;
+        };
+        match eye {
+            Eye::Center => 0.0,
+            Eye::Left => -stereo.interocular_distance / 2.0,
+            Eye::Right => stereo.interocular_distance / 2.0,
+        }
+    }
+
+    fn convergence_distance(&self) -> f64 {
+        match &self.stereo {
+            Some(stereo) => stereo.convergence_distance,
+            None => f64::INFINITY,
+        }
     }
 
     pub fn initialize(&mut self) {
-        self.image_height = (self.image_width as f64 / self.aspect_ratio) as usize;
+        self.scaled_width =
+            ((self.image_width as f64 * self.resolution_scale).round() as usize).max(1);
+        self.image_height = (self.scaled_width as f64 / self.aspect_ratio) as usize;
         self.image_height = if self.image_height < 1 {
             1
         } else {
             self.image_height
         };
 
-        self.pixel_samples_scale = 1.0 / self.sample_per_pixel as f64;
-
         self.center = self.look_from;
+        (self.render_vfov, self.render_defocus_angle) = match &self.physical {
+            Some(physical) => (physical.vfov(), physical.defocus_angle(self.focus_dist)),
+            None => (self.vfov, self.defocus_angle),
+        };
 
         // Determine viewport dimensions.
-        let theta = degrees_to_radian(self.vfov);
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * self.focus_dist;
-        let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64);
+        // The other projections map pixels to rays directly and only use the
+        // viewport for the camera frame.
+        let viewport_height = match self.projection {
+            Projection::Perspective
+            | Projection::Fisheye { .. }
+            | Projection::Equirectangular
+            | Projection::Realistic(_) => {
+                let theta = degrees_to_radian(self.render_vfov);
+                let h = (theta / 2.0).tan();
+                2.0 * h * self.focus_dist
+            }
+            Projection::Orthographic { view_height } => view_height,
+        };
+        let viewport_width =
+            viewport_height * (self.scaled_width as f64 / self.image_height as f64);
 
         self.w = Vec3::unit_vector(self.look_from - self.look_at);
         self.u = Vec3::unit_vector(Vec3::cross(self.vup, self.w));
@@ -108,54 +1077,1426 @@ impl Camera {
         let viewport_v = viewport_height * -self.v;
 
         // Calculate the horizontal and vertical delta vectors from pixel to pixel.
-        self.pixel_delta_u = viewport_u / self.image_width as f64;
+        self.pixel_delta_u = viewport_u / self.scaled_width as f64;
         self.pixel_delta_v = viewport_v / self.image_height as f64;
 
         // Calculate the location of the upper left pixel.
         let viewport_upper_left =
-            self.center - (self.focus_dist * self.w) - viewport_u / 2.0 - viewport_v / 2.0;
+            self.center - (self.focus_dist * self.w) - viewport_u / 2.0 - viewport_v / 2.0
+                + self.shift_x * viewport_u
+                - self.shift_y * viewport_v;
         self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
 
-        let defocus_radius = self.focus_dist * (degrees_to_radian(self.defocus_angle / 2.0).tan());
+        let defocus_radius =
+            self.focus_dist * (degrees_to_radian(self.render_defocus_angle / 2.0).tan());
         self.defocus_disk_u = defocus_radius * self.u;
         self.defocus_disk_v = defocus_radius * self.v;
+        self.focus_plane_normal = Vec3::unit_vector(
+            self.w
+                + degrees_to_radian(self.tilt_x).tan() * self.v
+                + degrees_to_radian(self.tilt_y).tan() * self.u,
+        );
+        self.focused_lens = match &self.projection {
+            Projection::Realistic(lens) => lens.focused(self.focus_dist),
+            _ => None,
+        };
+
+        self.crop = self.crop_bounds();
+        let (width, height) = self.output_size();
+        // Splatted samples land up to half a pixel plus the filter radius
+        // away from their pixel's center, and the denoiser reads pixels up to
+        // its radius away, so a crop needs a margin of both around it to
+        // match a full render.
+        let splat_margin = match self.filter_mode {
+            FilterMode::ImportanceSampling => 0,
+            FilterMode::Splatting => (self.filter.radius() + 0.5).ceil() as usize,
+        };
+        let denoise_margin = self.denoise.as_ref().map_or(0, |denoiser| denoiser.radius);
+        let margin = splat_margin + denoise_margin;
+        let x = self.crop.x.saturating_sub(margin);
+        let y = self.crop.y.saturating_sub(margin);
+        self.sample_bounds = PixelBounds {
+            x,
+            y,
+            width: (self.crop.x + self.crop.width + margin).min(width) - x,
+            height: (self.crop.y + self.crop.height + margin).min(height) - y,
+        };
     }
 
-    pub fn ray_color(&self, ray: &Ray, depth: usize, world: &impl Hittable) -> Color {
-        if let Some(rec) = world.hit(ray, Interval::new(0.001, f64::INFINITY)) {
-            if depth == 0 {
-                return Color::new(0.0, 0.0, 0.0);
-            }
-            if let Some((attenuation, scattered)) = rec.material.scatter(ray, &rec) {
-                return attenuation * Self::ray_color(&self, &scattered, depth - 1, world);
-            }
+    /// Pixels covered by the crop window, at least one.
+    fn crop_bounds(&self) -> PixelBounds {
+        let (image_width, image_height) = self.output_size();
+        let Some(window) = &self.crop_window else {
+            return PixelBounds {
+                x: 0,
+                y: 0,
+                width: image_width,
+                height: image_height,
+            };
+        };
+        let range = |min: f64, max: f64, size: usize| {
+            let start = ((min.clamp(0.0, 1.0) * size as f64).ceil() as usize).min(size - 1);
+            let end = ((max.clamp(0.0, 1.0) * size as f64).ceil() as usize).clamp(start + 1, size);
+            (start, end - start)
+        };
+        let (x, width) = range(window.x_min, window.x_max, image_width);
+        let (y, height) = range(window.y_min, window.y_max, image_height);
+        PixelBounds {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn ray_color(
+        &self,
+        ray: &Ray,
+        depth: usize,
+        world: &impl Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        match world.hit(ray, Interval::new(0.001, f64::INFINITY)) {
+            Some(rec) => self.shade(ray, &rec, depth, world, sampler),
+            None => Self::background(ray),
+        }
+    }
+
+    /// Like `ray_color` for a camera ray, also reporting what it hit first.
+    fn primary_ray_color(
+        &self,
+        ray: &Ray,
+        world: &impl Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> (Color, SurfaceFeatures) {
+        let Some(rec) = world.hit(ray, Interval::new(0.001, f64::INFINITY)) else {
+            return (Self::background(ray), SurfaceFeatures::background());
+        };
+
+        let features = SurfaceFeatures {
+            albedo: rec.material.albedo(&rec),
+            normal: rec.normal,
+            depth: Some(match self.projection {
+                Projection::Perspective
+                | Projection::Orthographic { .. }
+                | Projection::Realistic(_) => Vec3::dot(rec.p - self.center, -self.w),
+                Projection::Fisheye { .. } | Projection::Equirectangular => {
+                    Vec3::length(rec.p - self.center)
+                }
+            }),
+            position: rec.p,
+            material_id: rec.material_id,
+            object_id: rec.object_id,
+        };
+        let color = self.shade(ray, &rec, self.max_depth, world, sampler);
+        (color, features)
+    }
+
+    fn shade(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        depth: usize,
+        world: &impl Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        if depth == 0 {
             return Color::new(0.0, 0.0, 0.0);
         }
+        if let Some((attenuation, scattered)) = rec.material.scatter(ray, rec, sampler) {
+            return attenuation * Self::ray_color(self, &scattered, depth - 1, world, sampler);
+        }
+        Color::new(0.0, 0.0, 0.0)
+    }
 
+    fn background(ray: &Ray) -> Color {
         let unit_direction = Vec3::unit_vector(ray.direction);
         let a = 0.5 * (unit_direction.y + 1.0);
         (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
     }
 
-    fn get_ray(&self, i: usize, j: usize) -> Ray {
-        let offset = self.sample_square();
+    /// Ray through pixel (i, j) of the view of `eye`, displaced by `offset`
+    /// pixels from its center, or `None` if that point of the image sees
+    /// nothing.
+    fn get_ray(
+        &self,
+        eye: Eye,
+        i: usize,
+        j: usize,
+        offset: (f64, f64),
+        sampler: &mut dyn Sampler,
+    ) -> Option<Ray> {
+        // Position on the image in pixels, from its upper-left corner.
+        let x = i as f64 + 0.5 + offset.0;
+        let y = j as f64 + 0.5 + offset.1;
+        let (width, height) = (self.scaled_width as f64, self.image_height as f64);
+
+        let eye_shift = self.eye_shift(eye);
+        let (direction, eye_offset) = match self.projection {
+            Projection::Perspective | Projection::Orthographic { .. } => {
+                // Position relative to the image center, in half diagonals.
+                let half_diagonal = 0.5 * (width * width + height * height).sqrt();
+                let film_position = (
+                    (x - width / 2.0) / half_diagonal,
+                    (height / 2.0 - y) / half_diagonal,
+                );
+                return self.get_planar_ray(eye, i, j, offset, film_position, sampler);
+            }
+            Projection::Realistic(_) => {
+                let lens = self.focused_lens.as_ref()?;
+                let (film_width, film_height) = lens.film_size(width / height);
+                // The lens flips the image, so the film is read upside down.
+                let film_point = (
+                    (0.5 - x / width) * film_width,
+                    (y / height - 0.5) * film_height,
+                );
+                let (o, d) = lens.generate_ray(film_point, sample_unit_disk(sampler.get_2d()))?;
+                // The eyes are shifted without converging, see `Stereo`.
+                let origin =
+                    self.center + eye_shift * self.u + o.x * self.u + o.y * self.v + o.z * self.w;
+                return Some(Ray::new(origin, d.x * self.u + d.y * self.v + d.z * self.w));
+            }
+            Projection::Fisheye { mapping, fov } => {
+                let half_size = width.min(height) / 2.0;
+                let nx = (x - width / 2.0) / half_size;
+                let ny = (height / 2.0 - y) / half_size;
+                let r = (nx * nx + ny * ny).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let half_fov = degrees_to_radian(fov) / 2.0;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half_fov,
+                    FisheyeMapping::Equisolid => 2.0 * (r * (half_fov / 2.0).sin()).asin(),
+                };
+                let radial = if r > 0.0 {
+                    (nx / r) * self.u + (ny / r) * self.v
+                } else {
+                    Vec3::zero()
+                };
+                let direction = theta.sin() * radial - theta.cos() * self.w;
+                (direction, eye_shift * self.u)
+            }
+            Projection::Equirectangular => {
+                let phi = 2.0 * PI * (x / width - 0.5);
+                let latitude = PI * (0.5 - y / height);
+                let direction = latitude.cos() * phi.sin() * self.u + latitude.sin() * self.v
+                    - latitude.cos() * phi.cos() * self.w;
+                // Omni-directional stereo: the eyes sit on a circle, to either
+                // side of each horizontal viewing direction.
+                let right = phi.cos() * self.u + phi.sin() * self.w;
+                (direction, eye_shift * right)
+            }
+        };
+        // Aim from the eye at the point the center sees at the convergence
+        // distance.
+        let direction = Vec3::unit_vector(direction) - eye_offset / self.convergence_distance();
+        Some(Ray::new(self.center + eye_offset, direction))
+    }
+
+    /// Focus distance that puts the surface seen through the autofocus point
+    /// by a ray from the middle of the lens on the plane of focus, if it lies
+    /// in front of the camera and the lens can focus that close.
+    fn autofocus_distance(&self, world: &impl Hittable, autofocus: AutoFocus) -> Option<f64> {
+        let (width, height) = (self.scaled_width as f64, self.image_height as f64);
+        let (eye, x, y) = match autofocus {
+            AutoFocus::Center => (Eye::Center, width / 2.0, height / 2.0),
+            AutoFocus::Pixel { x, y } => {
+                let scale = width / self.image_width as f64;
+                let (x, y) = ((x as f64 + 0.5) * scale, (y as f64 + 0.5) * scale);
+                let (eye, i, j) = self.eye_pixel(x as usize, y as usize);
+                (eye, i as f64 + x.fract(), j as f64 + y.fract())
+            }
+        };
+        let offset = (x - 0.5, y - 0.5);
+        let ray = match self.projection {
+            Projection::Perspective | Projection::Orthographic { .. } => {
+                let (lens_center, focus_point) = self.planar_chief_ray(eye, 0, 0, offset);
+                Some(Ray::new(lens_center, focus_point - lens_center))
+            }
+            _ => self.get_ray(eye, 0, 0, offset, &mut CenterSampler),
+        };
+
+        let rec = world.hit(
+            &ray?.with_time(self.time).with_seed(self.seed),
+            Interval::new(0.001, f64::INFINITY),
+        )?;
+        // The plane of focus crosses the view axis `focus_dist` in front of
+        // the camera. Untilted, this is the depth of the hit point.
+        let n = self.focus_plane_normal;
+        let distance = Vec3::dot(self.center - rec.p, n) / Vec3::dot(self.w, n);
+        let in_range = distance > 0.0
+            && match &self.projection {
+                Projection::Realistic(lens) => lens.focused(distance).is_some(),
+                _ => true,
+            };
+        in_range.then_some(distance)
+    }
+
+    /// Ray of the perspective and orthographic projections, which go through
+    /// a point on the focus plane, or `None` if the lens barrel blocks it.
+    fn get_planar_ray(
+        &self,
+        eye: Eye,
+        i: usize,
+        j: usize,
+        offset: (f64, f64),
+        film_position: (f64, f64),
+        sampler: &mut dyn Sampler,
+    ) -> Option<Ray> {
+        let (lens_center, focus_point) = self.planar_chief_ray(eye, i, j, offset);
+        let ray_origin = if self.render_defocus_angle <= 0.0 {
+            lens_center
+        } else {
+            Self::defocus_disk_sample(self, lens_center, film_position, sampler)?
+        };
+        let ray_direction = focus_point - ray_origin;
+        Some(Ray::new(ray_origin, ray_direction))
+    }
+
+    /// Middle of the lens and the point on the focus plane that the planar
+    /// projections' rays through a point of the image pass through.
+    fn planar_chief_ray(
+        &self,
+        eye: Eye,
+        i: usize,
+        j: usize,
+        offset: (f64, f64),
+    ) -> (Point3, Point3) {
         let pixel_sample = self.pixel00_loc
-            + ((i as f64 + offset.x) * self.pixel_delta_u)
-            + ((j as f64 + offset.y) * self.pixel_delta_v);
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
+            + ((i as f64 + offset.0) * self.pixel_delta_u)
+            + ((j as f64 + offset.1) * self.pixel_delta_v);
+        let eye_offset = self.eye_shift(eye) * self.u;
+        // Orthographic rays start on the camera plane, straight behind their
+        // point on the focus plane. Perspective eyes share the center's
+        // viewport shifted so that the views line up at the convergence
+        // distance.
+        let (lens_center, focus_point) = match self.projection {
+            Projection::Orthographic { .. } => (
+                pixel_sample + self.focus_dist * self.w + eye_offset,
+                pixel_sample + eye_offset,
+            ),
+            _ => (
+                self.center + eye_offset,
+                pixel_sample + (1.0 - self.focus_dist / self.convergence_distance()) * eye_offset,
+            ),
+        };
+        // A tilted plane of focus is where the ray through the lens center
+        // meets it, rather than the plane through `pixel_sample`. Rays that
+        // run along the plane or meet it behind the lens never reach it and
+        // keep the untilted focus.
+        if self.tilt_x == 0.0 && self.tilt_y == 0.0 {
+            return (lens_center, focus_point);
+        }
+        let axis_point = self.center - self.focus_dist * self.w;
+        let chief = focus_point - lens_center;
+        let denominator = Vec3::dot(chief, self.focus_plane_normal);
+        let t = Vec3::dot(axis_point - lens_center, self.focus_plane_normal) / denominator;
+        if denominator.abs() < 1e-9 || t <= 0.0 {
+            (lens_center, focus_point)
         } else {
-            Self::defocus_disk_sample(&self)
+            (lens_center, lens_center + t * chief)
+        }
+    }
+
+    /// Point of the aperture around `lens_center`, or `None` if it lies in
+    /// the shadow of the lens barrel as seen from `film_position`.
+    fn defocus_disk_sample(
+        &self,
+        lens_center: Point3,
+        film_position: (f64, f64),
+        sampler: &mut dyn Sampler,
+    ) -> Option<Point3> {
+        let p = self.aperture.sample(sampler.get_2d());
+        // The barrel's opening is a circle just holding the aperture, sliding
+        // off it towards the edges of the image.
+        if self.optical_vignetting != 0.0 {
+            let radius = self.aperture.bounding_radius();
+            let dx = p.x - radius * self.optical_vignetting * film_position.0;
+            let dy = p.y - radius * self.optical_vignetting * film_position.1;
+            if dx * dx + dy * dy > radius * radius {
+                return None;
+            }
+        }
+        Some(lens_center + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v))
+    }
+    fn sample_square(&self, sampler: &mut dyn Sampler) -> (f64, f64) {
+        let (x, y) = sampler.get_2d();
+        (x - 0.5, y - 0.5)
+    }
+}
+
+/// Always takes the middle of the sample space, so lenses are sampled at
+/// their center.
+struct CenterSampler;
+
+impl Sampler for CenterSampler {
+    fn start_pixel_sample(&mut self, _pixel: (usize, usize), _sample_index: usize) {}
+
+    fn get_1d(&mut self) -> f64 {
+        0.5
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (0.5, 0.5)
+    }
+}
+
+/// A camera configuration that cannot produce a meaningful image.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CameraError {
+    InvalidAspectRatio(f64),
+    InvalidResolutionScale(f64),
+    /// The image would have no pixels in one direction.
+    EmptyImage {
+        width: usize,
+        height: usize,
+    },
+    /// A component of `look_from`, `look_at` or `vup` is infinite or NaN.
+    NonFinitePlacement,
+    LookFromEqualsLookAt,
+    VupParallelToView,
+    InvalidFieldOfView(f64),
+    InvalidViewHeight(f64),
+    InvalidFocusDistance(f64),
+    InvalidDefocusAngle(f64),
+    /// A polygonal aperture with fewer than three blades.
+    InvalidApertureBlades(usize),
+    /// A lens system without elements, or with non-finite or non-positive
+    /// dimensions.
+    InvalidLens,
+    /// A lens system that cannot bring the focus distance into focus.
+    LensCannotFocus(f64),
+    /// No samples per pixel, or adaptive sampling with a minimum above its
+    /// maximum.
+    InvalidSampleCount,
+    InvalidFilterRadius(f64),
+    InvalidGaussianSigma(f64),
+    InvalidLanczosTau(f64),
+    InvalidMitchellParameters {
+        b: f64,
+        c: f64,
+    },
+    /// The crop window is empty or reaches outside the image.
+    InvalidCropWindow,
+    /// A physical camera setting is not positive.
+    InvalidPhysicalCamera,
+    /// The physical camera's sensor and the image have different shapes.
+    SensorAspectMismatch {
+        sensor: f64,
+        image: f64,
+    },
+    /// The autofocus pixel lies outside the image.
+    InvalidAutoFocusPixel,
+    InvalidTime(f64),
+    InvalidExposure(f64),
+    /// A denoiser falloff is not positive.
+    InvalidDenoiser,
+    /// A tilt of the plane of focus is not strictly between -90 and 90
+    /// degrees.
+    InvalidTilt(f64),
+    /// Shift or tilt with a projection that does not support them.
+    UnsupportedLensMovement,
+    /// The interocular or convergence distance is not positive.
+    InvalidStereo,
+    /// Progressive rendering without any samples per pass.
+    InvalidProgressivePass,
+}
+
+impl fmt::Display for CameraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CameraError::InvalidAspectRatio(ratio) => {
+                write!(f, "aspect ratio must be positive, got {ratio}")
+            }
+            CameraError::InvalidResolutionScale(scale) => {
+                write!(f, "resolution scale must be positive, got {scale}")
+            }
+            CameraError::EmptyImage { width, height } => {
+                write!(f, "image of {width}x{height} pixels is empty")
+            }
+            CameraError::NonFinitePlacement => {
+                write!(f, "look_from, look_at and vup must be finite")
+            }
+            CameraError::LookFromEqualsLookAt => {
+                write!(f, "look_from and look_at are the same point")
+            }
+            CameraError::VupParallelToView => {
+                write!(f, "vup is zero or parallel to the view direction")
+            }
+            CameraError::InvalidFieldOfView(fov) => {
+                write!(f, "field of view {fov} is out of range")
+            }
+            CameraError::InvalidViewHeight(height) => {
+                write!(f, "orthographic view height must be positive, got {height}")
+            }
+            CameraError::InvalidFocusDistance(distance) => {
+                write!(f, "focus distance must be positive, got {distance}")
+            }
+            CameraError::InvalidDefocusAngle(angle) => {
+                write!(f, "defocus angle {angle} is out of range")
+            }
+            CameraError::InvalidLens => write!(f, "invalid lens system"),
+            CameraError::LensCannotFocus(distance) => {
+                write!(f, "lens system cannot focus at distance {distance}")
+            }
+            CameraError::InvalidApertureBlades(blades) => {
+                write!(
+                    f,
+                    "polygonal apertures need at least 3 blades, got {blades}"
+                )
+            }
+            CameraError::InvalidSampleCount => write!(f, "invalid number of samples per pixel"),
+            CameraError::InvalidFilterRadius(radius) => {
+                write!(f, "filter radius must be positive, got {radius}")
+            }
+            CameraError::InvalidGaussianSigma(sigma) => {
+                write!(f, "Gaussian filter sigma must be positive, got {sigma}")
+            }
+            CameraError::InvalidLanczosTau(tau) => {
+                write!(f, "Lanczos filter tau must be positive, got {tau}")
+            }
+            CameraError::InvalidMitchellParameters { b, c } => {
+                write!(
+                    f,
+                    "Mitchell filter parameters must be finite, got b = {b}, c = {c}"
+                )
+            }
+            CameraError::InvalidCropWindow => {
+                write!(f, "crop window must be a non-empty part of the image")
+            }
+            CameraError::InvalidPhysicalCamera => {
+                write!(f, "physical camera settings must be positive")
+            }
+            CameraError::SensorAspectMismatch { sensor, image } => write!(
+                f,
+                "sensor aspect ratio {sensor} does not match image aspect ratio {image}"
+            ),
+            CameraError::InvalidAutoFocusPixel => {
+                write!(f, "autofocus pixel must lie inside the image")
+            }
+            CameraError::InvalidTime(time) => write!(f, "time {time} is not finite"),
+            CameraError::InvalidExposure(exposure) => {
+                write!(f, "exposure {exposure} is not finite")
+            }
+            CameraError::InvalidDenoiser => {
+                write!(f, "denoiser falloffs must be positive")
+            }
+            CameraError::InvalidTilt(tilt) => {
+                write!(f, "tilt must be between -90 and 90 degrees, got {tilt}")
+            }
+            CameraError::UnsupportedLensMovement => {
+                write!(
+                    f,
+                    "shift and tilt need a perspective or orthographic projection"
+                )
+            }
+            CameraError::InvalidStereo => {
+                write!(
+                    f,
+                    "stereo interocular and convergence distances must be positive"
+                )
+            }
+            CameraError::InvalidProgressivePass => {
+                write!(
+                    f,
+                    "progressive passes must take at least one sample per pixel"
+                )
+            }
+        }
+    }
+}
+
+impl Error for CameraError {}
+
+impl From<CameraError> for io::Error {
+    fn from(error: CameraError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, error)
+    }
+}
+
+/// Builds a `Camera`, checking its configuration before handing it out.
+pub struct CameraBuilder {
+    camera: Camera,
+}
+
+impl Default for CameraBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraBuilder {
+    pub fn new() -> Self {
+        CameraBuilder {
+            camera: Camera::new(),
+        }
+    }
+
+    pub fn build(self) -> Result<Camera, CameraError> {
+        self.camera.validate()?;
+        Ok(self.camera)
+    }
+
+    pub fn aspect_ratio(mut self, aspect_ratio: f64) -> Self {
+        self.camera.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    pub fn image_width(mut self, image_width: usize) -> Self {
+        self.camera.image_width = image_width;
+        self
+    }
+
+    pub fn resolution_scale(mut self, resolution_scale: f64) -> Self {
+        self.camera.resolution_scale = resolution_scale;
+        self
+    }
+
+    pub fn crop_window(mut self, crop_window: CropWindow) -> Self {
+        self.camera.crop_window = Some(crop_window);
+        self
+    }
+
+    pub fn sample_per_pixel(mut self, sample_per_pixel: usize) -> Self {
+        self.camera.sample_per_pixel = sample_per_pixel;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.camera.max_depth = max_depth;
+        self
+    }
+
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.camera.projection = projection;
+        self
+    }
+
+    pub fn stereo(mut self, stereo: Stereo) -> Self {
+        self.camera.stereo = Some(stereo);
+        self
+    }
+
+    pub fn vfov(mut self, vfov: f64) -> Self {
+        self.camera.vfov = vfov;
+        self
+    }
+
+    pub fn look_from(mut self, look_from: Point3) -> Self {
+        self.camera.look_from = look_from;
+        self
+    }
+
+    pub fn look_at(mut self, look_at: Point3) -> Self {
+        self.camera.look_at = look_at;
+        self
+    }
+
+    pub fn vup(mut self, vup: Vec3) -> Self {
+        self.camera.vup = vup;
+        self
+    }
+
+    pub fn defocus_angle(mut self, defocus_angle: f64) -> Self {
+        self.camera.defocus_angle = defocus_angle;
+        self
+    }
+
+    pub fn focus_dist(mut self, focus_dist: f64) -> Self {
+        self.camera.focus_dist = focus_dist;
+        self
+    }
+
+    pub fn physical(mut self, physical: PhysicalCamera) -> Self {
+        self.camera.physical = Some(physical);
+        self
+    }
+
+    pub fn autofocus(mut self, autofocus: AutoFocus) -> Self {
+        self.camera.autofocus = Some(autofocus);
+        self
+    }
+
+    pub fn aperture(mut self, aperture: Aperture) -> Self {
+        self.camera.aperture = aperture;
+        self
+    }
+
+    pub fn optical_vignetting(mut self, optical_vignetting: f64) -> Self {
+        self.camera.optical_vignetting = optical_vignetting;
+        self
+    }
+
+    pub fn shift(mut self, shift_x: f64, shift_y: f64) -> Self {
+        self.camera.shift_x = shift_x;
+        self.camera.shift_y = shift_y;
+        self
+    }
+
+    pub fn tilt(mut self, tilt_x: f64, tilt_y: f64) -> Self {
+        self.camera.tilt_x = tilt_x;
+        self.camera.tilt_y = tilt_y;
+        self
+    }
+
+    pub fn sampler(mut self, sampler: SamplerType) -> Self {
+        self.camera.sampler = sampler;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.camera.seed = seed;
+        self
+    }
+
+    pub fn time(mut self, time: f64) -> Self {
+        self.camera.time = time;
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter, filter_mode: FilterMode) -> Self {
+        self.camera.filter = filter;
+        self.camera.filter_mode = filter_mode;
+        self
+    }
+
+    pub fn adaptive(mut self, adaptive: AdaptiveSampling) -> Self {
+        self.camera.adaptive = Some(adaptive);
+        self
+    }
+
+    pub fn progressive(mut self, progressive: Progressive) -> Self {
+        self.camera.progressive = Some(progressive);
+        self
+    }
+
+    pub fn checkpoint(mut self, checkpoint: Checkpoint) -> Self {
+        self.camera.checkpoint = Some(checkpoint);
+        self
+    }
+
+    pub fn denoise(mut self, denoiser: Denoiser) -> Self {
+        self.camera.denoise = Some(denoiser);
+        self
+    }
+
+    pub fn exposure(mut self, exposure: f64) -> Self {
+        self.camera.exposure = exposure;
+        self
+    }
+
+    pub fn tone_mapper(mut self, tone_mapper: ToneMapper) -> Self {
+        self.camera.tone_mapper = tone_mapper;
+        self
+    }
+
+    pub fn aov_output(mut self, aov_output: AovOutput) -> Self {
+        self.camera.aov_output = Some(aov_output);
+        self
+    }
+
+    pub fn output_path(mut self, output_path: impl Into<PathBuf>) -> Self {
+        self.camera.output_path = Some(output_path.into());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        animation::{Interpolation, Track},
+        hittable_list::HittableList,
+        lens::LensElement,
+        material::{AlphaMask, Lambertian},
+        sphere::Sphere,
+        texture::SolidColor,
+    };
+
+    /// A ground sphere and a half transparent sphere in front of the camera.
+    fn scene() -> HittableList<'static> {
+        let mut world = HittableList {
+            objects: Vec::new(),
         };
-        let ray_direction = pixel_sample - ray_origin;
-        Ray::new(ray_origin, ray_direction)
+        world.add(Sphere::new(
+            Point3::new(0.0, -100.5, -1.0),
+            100.0,
+            Lambertian::new(Color::new(0.5, 0.6, 0.3)),
+        ));
+        world.add(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            AlphaMask::new(
+                Lambertian::new(Color::new(0.7, 0.2, 0.2)),
+                SolidColor::new(Color::new(0.5, 0.5, 0.5)),
+            ),
+        ));
+        world
+    }
+
+    fn camera() -> CameraBuilder {
+        Camera::builder()
+            .aspect_ratio(1.0)
+            .image_width(12)
+            .sample_per_pixel(8)
+            .max_depth(4)
+    }
+
+    /// The complete state of a film, for exact comparisons.
+    fn state(film: &Film) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        film.write_state(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn render(camera: CameraBuilder) -> Film {
+        camera.build().unwrap().render_film(&scene()).unwrap()
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = Point3::random_in_unit_disk();
-        self.center + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ray_tracer_{}_{name}", std::process::id()))
     }
-    fn sample_square(&self) -> Vec3 {
-        Vec3::new(random_f64() - 0.5, random_f64() - 0.5, 0.0)
+
+    #[test]
+    fn sequences_leave_the_camera_as_it_was() {
+        let mut camera = camera().time(0.25).build().unwrap();
+        let animation = CameraAnimation {
+            look_from: Some(
+                Track::new(Interpolation::Linear)
+                    .with_key(0.0, Point3::new(0.0, 0.0, 1.0))
+                    .with_key(1.0, Point3::new(1.0, 0.0, 1.0)),
+            ),
+            look_at: Some(Track::new(Interpolation::Linear).with_key(0.0, Point3::zero())),
+            vfov: Some(Track::new(Interpolation::Linear).with_key(0.0, 40.0)),
+            focus_dist: Some(Track::new(Interpolation::Linear).with_key(0.0, 2.0)),
+        };
+        let sequence = FrameSequence::new(temp_path("sequence"), 0..2);
+        let before = (
+            camera.look_from,
+            camera.look_at,
+            camera.vfov,
+            camera.focus_dist,
+            camera.time,
+            camera.seed,
+        );
+
+        let result = camera.render_sequence(&scene(), &animation, &sequence);
+        for frame in sequence.frames.clone() {
+            fs::remove_file(sequence.path(frame)).unwrap();
+        }
+        result.unwrap();
+
+        let after = (
+            camera.look_from,
+            camera.look_at,
+            camera.vfov,
+            camera.focus_dist,
+            camera.time,
+            camera.seed,
+        );
+        // Vec3 has no PartialEq; Debug output prints the exact values.
+        assert_eq!(format!("{after:?}"), format!("{before:?}"));
+        assert_eq!(camera.output_path, None);
+    }
+
+    #[test]
+    fn physical_exposure_follows_the_exposure_value() {
+        // f/8 at 1/125 s and ISO 100 is about EV 13.
+        let physical = PhysicalCamera::new(50.0, 8.0);
+        assert!((physical.ev100() - 8000f64.log2()).abs() < 1e-12);
+        assert!((physical.ev100() - 12.966).abs() < 1e-3);
+        assert!((physical.exposure() + 13.229).abs() < 1e-3);
+
+        let faster = PhysicalCamera {
+            shutter_time: 1.0 / 250.0,
+            ..physical.clone()
+        };
+        assert!((faster.ev100() - physical.ev100() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn physical_cameras_leave_the_lens_settings_alone() {
+        let physical = PhysicalCamera::new(50.0, 8.0);
+        let mut camera = camera()
+            .aspect_ratio(1.5)
+            .physical(physical.clone())
+            .vfov(30.0)
+            .defocus_angle(2.0)
+            .build()
+            .unwrap();
+        camera.render_film(&scene()).unwrap();
+        assert_eq!((camera.vfov, camera.defocus_angle), (30.0, 2.0));
+        assert_eq!(camera.render_vfov, physical.vfov());
+        assert_eq!(
+            camera.render_defocus_angle,
+            physical.defocus_angle(camera.focus_dist)
+        );
+    }
+
+    #[test]
+    fn rejects_empty_filters() {
+        for radius in [0.0, -1.0, f64::NAN] {
+            let filter = Filter::Tent { radius };
+            let result = camera()
+                .filter(filter, FilterMode::ImportanceSampling)
+                .build();
+            assert!(matches!(result, Err(CameraError::InvalidFilterRadius(_))));
+        }
+    }
+
+    #[test]
+    fn rejects_degenerate_filter_shapes() {
+        let filter = |filter| camera().filter(filter, FilterMode::ImportanceSampling);
+        for sigma in [0.0, -0.5, f64::NAN, f64::INFINITY] {
+            let error = error(filter(Filter::Gaussian { radius: 1.5, sigma }));
+            assert!(matches!(error, CameraError::InvalidGaussianSigma(_)));
+        }
+        for tau in [0.0, -3.0, f64::NAN, f64::INFINITY] {
+            let error = error(filter(Filter::Lanczos { radius: 3.0, tau }));
+            assert!(matches!(error, CameraError::InvalidLanczosTau(_)));
+        }
+        for (b, c) in [(f64::NAN, 0.3), (0.3, f64::INFINITY)] {
+            let error = error(filter(Filter::Mitchell { radius: 2.0, b, c }));
+            assert!(matches!(
+                error,
+                CameraError::InvalidMitchellParameters { .. }
+            ));
+        }
+        assert!(
+            filter(Filter::Mitchell {
+                radius: 2.0,
+                b: 1.0,
+                c: -0.5
+            })
+            .build()
+            .is_ok()
+        );
+    }
+
+    /// The error `build` returns for a camera.
+    fn error(builder: CameraBuilder) -> CameraError {
+        builder.build().err().expect("camera should be rejected")
+    }
+
+    #[test]
+    fn accepts_the_test_camera() {
+        assert!(camera().build().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_aspect_ratios() {
+        for ratio in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let error = error(camera().aspect_ratio(ratio));
+            assert!(matches!(error, CameraError::InvalidAspectRatio(_)));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_resolution_scales() {
+        for scale in [0.0, -0.5, f64::NAN] {
+            let error = error(camera().resolution_scale(scale));
+            assert!(matches!(error, CameraError::InvalidResolutionScale(_)));
+        }
+    }
+
+    #[test]
+    fn rejects_empty_images() {
+        assert_eq!(
+            error(camera().image_width(0)),
+            CameraError::EmptyImage {
+                width: 0,
+                height: 0
+            }
+        );
+        assert_eq!(
+            error(camera().aspect_ratio(100.0)),
+            CameraError::EmptyImage {
+                width: 12,
+                height: 0
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_degenerate_view_directions() {
+        let origin = Point3::new(1.0, 2.0, 3.0);
+        assert_eq!(
+            error(camera().look_from(origin).look_at(origin)),
+            CameraError::LookFromEqualsLookAt
+        );
+        assert_eq!(
+            error(camera().vup(Vec3::zero())),
+            CameraError::VupParallelToView
+        );
+        assert_eq!(
+            error(camera().vup(Vec3::new(0.0, 0.0, 2.0))),
+            CameraError::VupParallelToView
+        );
+    }
+
+    #[test]
+    fn rejects_non_finite_placements() {
+        let broken = [
+            Point3::new(f64::NAN, 0.0, 0.0),
+            Point3::new(0.0, f64::INFINITY, 0.0),
+            Point3::new(0.0, 0.0, f64::NEG_INFINITY),
+        ];
+        for v in broken {
+            for builder in [camera().look_from(v), camera().look_at(v), camera().vup(v)] {
+                assert_eq!(error(builder), CameraError::NonFinitePlacement);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_non_finite_times_and_exposures() {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let time = error(camera().time(value));
+            assert!(matches!(time, CameraError::InvalidTime(_)));
+            let exposure = error(camera().exposure(value));
+            assert!(matches!(exposure, CameraError::InvalidExposure(_)));
+        }
+        assert!(camera().time(-2.5).exposure(-3.0).build().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_fields_of_view() {
+        for vfov in [0.0, 180.0, f64::NAN] {
+            let error = error(camera().vfov(vfov));
+            assert!(matches!(error, CameraError::InvalidFieldOfView(_)));
+        }
+        for fov in [0.0, 361.0] {
+            let fisheye = Projection::Fisheye {
+                mapping: FisheyeMapping::Equidistant,
+                fov,
+            };
+            let error = error(camera().projection(fisheye));
+            assert!(matches!(error, CameraError::InvalidFieldOfView(_)));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_view_heights() {
+        for view_height in [0.0, -1.0, f64::INFINITY] {
+            let orthographic = Projection::Orthographic { view_height };
+            let error = error(camera().projection(orthographic));
+            assert!(matches!(error, CameraError::InvalidViewHeight(_)));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_focus_distances() {
+        for distance in [0.0, -1.0, f64::INFINITY, f64::NAN] {
+            let error = error(camera().focus_dist(distance));
+            assert!(matches!(error, CameraError::InvalidFocusDistance(_)));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_defocus_angles() {
+        for angle in [-1.0, 180.0, f64::NAN] {
+            let error = error(camera().defocus_angle(angle));
+            assert!(matches!(error, CameraError::InvalidDefocusAngle(_)));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_lens_systems() {
+        // A 100 mm biconvex lens.
+        let element = |radius, thickness, ior| LensElement {
+            radius,
+            thickness,
+            ior,
+            aperture: 20.0,
+        };
+        let lens = LensSystem::new(vec![element(100.0, 1.0, 1.5), element(-100.0, 100.0, 0.0)]);
+        let realistic =
+            |lens: &LensSystem| camera().projection(Projection::Realistic(lens.clone()));
+        assert!(realistic(&lens).focus_dist(1.0).build().is_ok());
+
+        let broken = [
+            LensSystem::new(Vec::new()),
+            LensSystem::new(vec![element(100.0, 0.0, 1.5), element(-100.0, 100.0, 0.0)]),
+            LensSystem::new(vec![
+                element(f64::NAN, 1.0, 1.5),
+                element(-100.0, 100.0, 0.0),
+            ]),
+            LensSystem {
+                film_diagonal: 0.0,
+                ..lens.clone()
+            },
+            LensSystem {
+                scene_units_per_mm: f64::INFINITY,
+                ..lens.clone()
+            },
+        ];
+        for lens in &broken {
+            assert_eq!(error(realistic(lens)), CameraError::InvalidLens, "{lens:?}");
+        }
+        // Closer than four focal lengths, no real image reaches the film.
+        assert_eq!(
+            error(realistic(&lens).focus_dist(0.3)),
+            CameraError::LensCannotFocus(0.3)
+        );
+    }
+
+    #[test]
+    fn rejects_apertures_with_too_few_blades() {
+        for blades in 0..3 {
+            let aperture = Aperture::Polygon {
+                blades,
+                rotation: 0.0,
+            };
+            assert_eq!(
+                error(camera().aperture(aperture)),
+                CameraError::InvalidApertureBlades(blades)
+            );
+        }
+        let triangle = Aperture::Polygon {
+            blades: 3,
+            rotation: 0.0,
+        };
+        assert!(camera().aperture(triangle).build().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_sample_counts() {
+        assert_eq!(
+            error(camera().sample_per_pixel(0)),
+            CameraError::InvalidSampleCount
+        );
+        assert_eq!(
+            error(camera().adaptive(AdaptiveSampling::new(16, 8, 0.01))),
+            CameraError::InvalidSampleCount
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_crop_windows() {
+        for window in [
+            CropWindow::new(0.5, 0.5, 0.0, 1.0),
+            CropWindow::new(0.0, 1.0, 0.8, 0.2),
+            CropWindow::new(-0.1, 0.5, 0.0, 1.0),
+            CropWindow::new(0.0, 1.0, 0.0, 1.5),
+        ] {
+            assert_eq!(
+                error(camera().crop_window(window)),
+                CameraError::InvalidCropWindow
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_physical_cameras() {
+        for physical in [
+            PhysicalCamera::new(0.0, 8.0),
+            PhysicalCamera::new(50.0, -2.0),
+        ] {
+            assert_eq!(
+                error(camera().physical(physical)),
+                CameraError::InvalidPhysicalCamera
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_sensors_shaped_unlike_the_image() {
+        let full_frame = PhysicalCamera::new(50.0, 8.0);
+        assert_eq!(
+            error(camera().physical(full_frame.clone())),
+            CameraError::SensorAspectMismatch {
+                sensor: 1.5,
+                image: 1.0
+            }
+        );
+        assert!(
+            camera()
+                .aspect_ratio(1.5)
+                .physical(full_frame.clone())
+                .build()
+                .is_ok()
+        );
+        let square = PhysicalCamera {
+            sensor_width: 24.0,
+            ..full_frame
+        };
+        assert!(camera().physical(square).build().is_ok());
+    }
+
+    #[test]
+    fn rejects_autofocus_pixels_outside_the_image() {
+        for (x, y) in [(12, 0), (0, 12)] {
+            assert_eq!(
+                error(camera().autofocus(AutoFocus::Pixel { x, y })),
+                CameraError::InvalidAutoFocusPixel
+            );
+        }
+        assert!(
+            camera()
+                .autofocus(AutoFocus::Pixel { x: 11, y: 11 })
+                .build()
+                .is_ok()
+        );
+
+        // 12 / 0.7 rounds down to 17 rows.
+        let narrow = camera().aspect_ratio(0.7);
+        assert_eq!(
+            error(narrow.autofocus(AutoFocus::Pixel { x: 0, y: 17 })),
+            CameraError::InvalidAutoFocusPixel
+        );
+    }
+
+    #[test]
+    fn autofocus_pixels_count_in_the_written_stereo_image() {
+        for (layout, (columns, rows)) in [
+            (StereoLayout::SideBySide, (24, 12)),
+            (StereoLayout::TopBottom, (12, 24)),
+        ] {
+            let stereo = Stereo {
+                layout,
+                ..Stereo::new(0.1, 2.0)
+            };
+            let stereo_camera = || camera().stereo(stereo.clone());
+            let last = AutoFocus::Pixel {
+                x: columns - 1,
+                y: rows - 1,
+            };
+            assert!(stereo_camera().autofocus(last).build().is_ok());
+            for (x, y) in [(columns, 0), (0, rows)] {
+                assert_eq!(
+                    error(stereo_camera().autofocus(AutoFocus::Pixel { x, y })),
+                    CameraError::InvalidAutoFocusPixel
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn autofocus_puts_the_subject_on_a_tilted_plane_of_focus() {
+        let mut world = HittableList {
+            objects: Vec::new(),
+        };
+        world.add(Sphere::new(
+            Point3::new(0.0, -100.5, -1.0),
+            100.0,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+        let (x, y) = (3, 10);
+        let mut camera = camera()
+            .tilt(20.0, 10.0)
+            .defocus_angle(2.0)
+            .autofocus(AutoFocus::Pixel { x, y })
+            .build()
+            .unwrap();
+        camera.render_film(&world).unwrap();
+
+        let (lens_center, focus_point) = camera.planar_chief_ray(Eye::Center, x, y, (0.0, 0.0));
+        let ray = Ray::new(lens_center, focus_point - lens_center);
+        let rec = world
+            .hit(&ray, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert!(Vec3::length(rec.p - focus_point) < 1e-9);
+    }
+
+    #[test]
+    fn rejects_invalid_denoisers() {
+        for denoiser in [
+            Denoiser {
+                sigma_spatial: 0.0,
+                ..Denoiser::default()
+            },
+            Denoiser {
+                sigma_color: f64::NAN,
+                ..Denoiser::default()
+            },
+            Denoiser {
+                sigma_albedo: -0.1,
+                ..Denoiser::default()
+            },
+            Denoiser {
+                sigma_normal: f64::INFINITY,
+                ..Denoiser::default()
+            },
+            Denoiser {
+                sigma_depth: 0.0,
+                ..Denoiser::default()
+            },
+        ] {
+            assert_eq!(
+                error(camera().denoise(denoiser)),
+                CameraError::InvalidDenoiser
+            );
+        }
+        assert!(camera().denoise(Denoiser::default()).build().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_stereo_distances() {
+        for stereo in [
+            Stereo::new(0.0, 5.0),
+            Stereo::new(f64::INFINITY, 5.0),
+            Stereo::new(0.1, 0.0),
+            Stereo::new(0.1, f64::NAN),
+        ] {
+            assert_eq!(error(camera().stereo(stereo)), CameraError::InvalidStereo);
+        }
+        assert!(
+            camera()
+                .stereo(Stereo::new(0.1, f64::INFINITY))
+                .build()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn rejects_empty_progressive_passes() {
+        assert_eq!(
+            error(camera().progressive(Progressive::new(0))),
+            CameraError::InvalidProgressivePass
+        );
+    }
+
+    #[test]
+    fn rejects_tilts_of_90_degrees_or_more() {
+        for tilt in [90.0, -90.0, 120.0, f64::NAN] {
+            let result = camera().tilt(tilt, 0.0).build();
+            assert!(matches!(result, Err(CameraError::InvalidTilt(_))));
+            let result = camera().tilt(0.0, tilt).build();
+            assert!(matches!(result, Err(CameraError::InvalidTilt(_))));
+        }
+        assert!(camera().tilt(89.0, -89.0).build().is_ok());
+    }
+
+    #[test]
+    fn rejects_lens_movements_without_a_planar_projection() {
+        let result = camera()
+            .projection(Projection::Equirectangular)
+            .shift(0.1, 0.0)
+            .build();
+        assert_eq!(result.err(), Some(CameraError::UnsupportedLensMovement));
+        let result = camera()
+            .projection(Projection::Equirectangular)
+            .tilt(10.0, 0.0)
+            .build();
+        assert_eq!(result.err(), Some(CameraError::UnsupportedLensMovement));
+        let orthographic = Projection::Orthographic { view_height: 2.0 };
+        assert!(
+            camera()
+                .projection(orthographic)
+                .shift(0.1, 0.0)
+                .build()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn renders_depend_only_on_the_seed() {
+        let first = render(camera().seed(1));
+        let again = render(camera().seed(1));
+        let other = render(camera().seed(2));
+        assert_eq!(state(&first), state(&again));
+        assert_ne!(state(&first), state(&other));
+    }
+
+    #[test]
+    fn resumed_render_matches_uninterrupted_one() {
+        let path = temp_path("resume.ckpt");
+        let checkpoint = Checkpoint::new(&path, Duration::ZERO);
+
+        // Stop after the first pass, as if the render had been interrupted.
+        let mut progressive = Progressive::new(3);
+        progressive.time_budget = Some(Duration::ZERO);
+        let interrupted = render(
+            camera()
+                .seed(5)
+                .progressive(progressive)
+                .checkpoint(checkpoint.clone()),
+        );
+        let resumed = render(
+            camera()
+                .seed(5)
+                .checkpoint(checkpoint.clone().with_resume(true)),
+        );
+        let mismatched = camera()
+            .seed(5)
+            .filter(Filter::Tent { radius: 1.0 }, FilterMode::ImportanceSampling)
+            .checkpoint(checkpoint.with_resume(true))
+            .build()
+            .unwrap()
+            .render_film(&scene());
+        fs::remove_file(&path).unwrap();
+
+        let uninterrupted = render(camera().seed(5));
+        assert_eq!(interrupted.sample_count(0, 0), 3);
+        assert_eq!(state(&resumed), state(&uninterrupted));
+        assert!(mismatched.is_err());
+    }
+
+    fn bits(image: &[Color]) -> Vec<[u64; 3]> {
+        image
+            .iter()
+            .map(|c| [c.r.to_bits(), c.g.to_bits(), c.b.to_bits()])
+            .collect()
+    }
+
+    #[test]
+    fn crop_matches_region_of_full_render() {
+        for mode in [FilterMode::ImportanceSampling, FilterMode::Splatting] {
+            let base = || {
+                camera()
+                    .image_width(20)
+                    .seed(3)
+                    .filter(Filter::Tent { radius: 1.0 }, mode)
+                    .denoise(Denoiser {
+                        radius: 2,
+                        ..Denoiser::default()
+                    })
+            };
+            let mut full = base().build().unwrap();
+            let full_film = full.render_film(&scene()).unwrap();
+            let full_image = full.develop(&full_film);
+
+            let mut cropped = base()
+                .crop_window(CropWindow::new(0.3, 0.7, 0.2, 0.6))
+                .build()
+                .unwrap();
+            let cropped_film = cropped.render_film(&scene()).unwrap();
+            let cropped_image = cropped.develop(&cropped_film);
+
+            let crop = cropped.crop;
+            assert!(cropped.sample_bounds.width < full.sample_bounds.width);
+            let region: Vec<Color> = (crop.y..crop.y + crop.height)
+                .flat_map(|y| &full_image[y * 20 + crop.x..y * 20 + crop.x + crop.width])
+                .copied()
+                .collect();
+            assert_eq!(bits(&cropped_image), bits(&region), "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn stereo_views_are_denoised_apart() {
+        let denoiser = Denoiser {
+            radius: 3,
+            ..Denoiser::default()
+        };
+        let mut camera = camera()
+            .seed(4)
+            .stereo(Stereo::new(0.1, 2.0))
+            .denoise(denoiser.clone())
+            .build()
+            .unwrap();
+        let film = camera.render_film(&scene()).unwrap();
+        let image = camera.develop(&film);
+
+        for x in [0, 12] {
+            let view = film.crop(x, 0, 12, 12);
+            let expected = denoiser.apply(&view, &view.resolve());
+            let developed: Vec<Color> = (0..12)
+                .flat_map(|y| &image[y * 24 + x..y * 24 + x + 12])
+                .copied()
+                .collect();
+            assert_eq!(bits(&developed), bits(&expected));
+        }
     }
 }
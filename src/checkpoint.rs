@@ -0,0 +1,189 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::film::Film;
+
+/// Periodically saves the state of a render to `path` so that it can be
+/// resumed after a crash or interruption.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub path: PathBuf,
+    /// Minimum time between two saves. Saves happen between scanlines.
+    pub interval: Duration,
+    /// Continue from the checkpoint at `path` if one exists, instead of
+    /// overwriting it. Off by default.
+    pub resume: bool,
+}
+
+impl Checkpoint {
+    pub fn new(path: impl Into<PathBuf>, interval: Duration) -> Self {
+        Checkpoint {
+            path: path.into(),
+            interval,
+            resume: false,
+        }
+    }
+
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+}
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 1;
+
+/// Everything needed besides the film to continue a render where it stopped.
+/// Random numbers are derived from the seed, pixel and sample index, so the
+/// per-pixel sample counts stored in the film are the whole generator state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CheckpointHeader {
+    /// Position of the film's upper-left pixel in the full image, nonzero
+    /// when only a region is rendered.
+    pub x_offset: usize,
+    pub y_offset: usize,
+    pub width: usize,
+    pub height: usize,
+    pub seed: u64,
+    /// Hash of every camera setting that affects the samples, so that a
+    /// checkpoint is only resumed by the render that wrote it.
+    pub settings: u64,
+    pub max_samples: usize,
+    /// Samples per pixel completed by every pass before the current one.
+    pub samples_done: usize,
+}
+
+/// Writes a checkpoint, replacing any previous one only once the new file is
+/// complete.
+pub fn save(path: &Path, header: &CheckpointHeader, film: &Film) -> io::Result<()> {
+    let mut partial = path.to_path_buf().into_os_string();
+    partial.push(".partial");
+
+    let mut out = BufWriter::new(File::create(&partial)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    for value in [
+        header.x_offset as u64,
+        header.y_offset as u64,
+        header.width as u64,
+        header.height as u64,
+        header.seed,
+        header.settings,
+        header.max_samples as u64,
+        header.samples_done as u64,
+    ] {
+        write_u64(&mut out, value)?;
+    }
+    film.write_state(&mut out)?;
+    out.into_inner()?.sync_all()?;
+
+    fs::rename(&partial, path)
+}
+
+pub fn load(path: &Path) -> io::Result<(CheckpointHeader, Film)> {
+    let mut input = BufReader::new(File::open(path)?);
+
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+    let mut version = [0u8; 4];
+    input.read_exact(&mut version)?;
+    if &magic != MAGIC || u32::from_le_bytes(version) != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a compatible checkpoint file",
+        ));
+    }
+
+    let header = CheckpointHeader {
+        x_offset: read_u64(&mut input)? as usize,
+        y_offset: read_u64(&mut input)? as usize,
+        width: read_u64(&mut input)? as usize,
+        height: read_u64(&mut input)? as usize,
+        seed: read_u64(&mut input)?,
+        settings: read_u64(&mut input)?,
+        max_samples: read_u64(&mut input)? as usize,
+        samples_done: read_u64(&mut input)? as usize,
+    };
+    let film = Film::read_state(&mut input, header.width, header.height)?;
+    Ok((header, film))
+}
+
+pub(crate) fn write_u64(out: &mut impl Write, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn write_f64(out: &mut impl Write, value: f64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, film::SurfaceFeatures, vec3::Vec3};
+
+    #[test]
+    fn round_trips_header_and_film() {
+        let header = CheckpointHeader {
+            x_offset: 3,
+            y_offset: 4,
+            width: 2,
+            height: 3,
+            seed: 42,
+            settings: 0x0123_4567_89ab_cdef,
+            max_samples: 16,
+            samples_done: 8,
+        };
+        let mut film = Film::new(header.width, header.height);
+        let features = SurfaceFeatures {
+            albedo: Color::new(0.2, 0.4, 0.6),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            depth: Some(2.5),
+            position: Vec3::new(1.0, 2.0, 3.0),
+            material_id: 7,
+            object_id: 9,
+        };
+        for (x, y) in [(0, 0), (1, 2), (1, 2)] {
+            film.add_sample(x, y, Color::new(0.1, 1.5, 0.3), 0.75);
+            film.record_estimate(x, y, Color::new(0.1, 1.5, 0.3));
+            film.record_features(x, y, &features);
+        }
+
+        let path = std::env::temp_dir().join(format!("ray_tracer_{}.ckpt", std::process::id()));
+        save(&path, &header, &film).unwrap();
+        let (loaded_header, loaded_film) = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded_header, header);
+        let state = |film: &Film| {
+            let mut bytes = Vec::new();
+            film.write_state(&mut bytes).unwrap();
+            bytes
+        };
+        assert_eq!(state(&loaded_film), state(&film));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = std::env::temp_dir().join(format!("ray_tracer_{}.bad", std::process::id()));
+        fs::write(&path, b"P3\n1 1\n255\n0 0 0\n").unwrap();
+        let result = load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    io::Write,
+    io::{self, Write},
     ops::{Add, Mul},
 };
 
@@ -16,23 +16,46 @@ impl Color {
     pub fn new(r: f64, g: f64, b: f64) -> Self {
         Color { r, g, b }
     }
+
+    /// Relative luminance of a linear Rec. 709 color.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
 }
 
 const INTENSITY: Interval = Interval::new(0.0, 0.999);
 
-pub fn write_color(out: &mut impl Write, pixel_color: Color) {
-    let rbyte: usize = (256.0 * INTENSITY.clamp(linear_to_gamma(pixel_color.r))) as usize;
-    let gbyte = (256.0 * INTENSITY.clamp(linear_to_gamma(pixel_color.g))) as usize;
-    let bbyte = (256.0 * INTENSITY.clamp(linear_to_gamma(pixel_color.b))) as usize;
+pub fn write_color(out: &mut impl Write, pixel_color: Color) -> io::Result<()> {
+    let rbyte: usize = (256.0 * INTENSITY.clamp(linear_to_srgb(pixel_color.r))) as usize;
+    let gbyte = (256.0 * INTENSITY.clamp(linear_to_srgb(pixel_color.g))) as usize;
+    let bbyte = (256.0 * INTENSITY.clamp(linear_to_srgb(pixel_color.b))) as usize;
 
-    writeln!(out, "{rbyte} {gbyte} {bbyte}").unwrap();
+    writeln!(out, "{rbyte} {gbyte} {bbyte}")
+}
+
+/// Writes `pixels`, row by row, as an ASCII PPM image.
+pub fn write_ppm(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+) -> io::Result<()> {
+    writeln!(out, "P3\n {} {}\n255", width, height)?;
+    for &pixel in pixels {
+        write_color(out, pixel)?;
+    }
+    Ok(())
 }
 
-fn linear_to_gamma(linear_component: f64) -> f64 {
-    if linear_component > 0.0 {
-        return linear_component.sqrt();
+/// The sRGB transfer function (IEC 61966-2-1).
+fn linear_to_srgb(linear_component: f64) -> f64 {
+    if linear_component <= 0.0 {
+        return 0.0;
     }
-    0.0
+    if linear_component <= 0.0031308 {
+        return 12.92 * linear_component;
+    }
+    1.055 * linear_component.powf(1.0 / 2.4) - 0.055
 }
 
 impl From<Vec3> for Color {
@@ -68,3 +91,22 @@ impl Mul<Color> for Color {
         Color::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_transfer_function_is_monotonic_and_fixes_its_ends() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-12);
+        let encoded: Vec<f64> = (0..=1000)
+            .map(|i| linear_to_srgb(i as f64 / 1000.0))
+            .collect();
+        assert!(encoded.windows(2).all(|pair| pair[0] < pair[1]));
+        // Both pieces meet at the breakpoint.
+        let below = linear_to_srgb(0.0031308);
+        let above = 1.055 * 0.0031308f64.powf(1.0 / 2.4) - 0.055;
+        assert!((below - above).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{color::Color, film::Film, vec3::Vec3};
+
+/// Joint (cross) bilateral filter guided by the film's albedo, normal and
+/// depth buffers. Lighting is separated from surface color by dividing out
+/// the albedo before filtering and multiplying it back afterwards, so texture
+/// detail survives while sampling noise is smoothed away.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Denoiser {
+    /// Half width of the filter window in pixels.
+    pub radius: usize,
+    /// Falloff with screen-space distance, in pixels.
+    pub sigma_spatial: f64,
+    /// Falloff with difference in (demodulated) color.
+    pub sigma_color: f64,
+    /// Falloff with difference in albedo.
+    pub sigma_albedo: f64,
+    /// Falloff with `1 - cos` of the angle between normals.
+    pub sigma_normal: f64,
+    /// Falloff with depth difference, relative to the center pixel's depth.
+    pub sigma_depth: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            radius: 5,
+            sigma_spatial: 3.0,
+            sigma_color: 0.6,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.1,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+// Keeps demodulation stable where the albedo is black.
+const ALBEDO_EPSILON: f64 = 0.01;
+
+impl Denoiser {
+    /// Whether every falloff is positive and finite.
+    pub(crate) fn is_valid(&self) -> bool {
+        [
+            self.sigma_spatial,
+            self.sigma_color,
+            self.sigma_albedo,
+            self.sigma_normal,
+            self.sigma_depth,
+        ]
+        .iter()
+        .all(|&sigma| sigma.is_finite() && sigma > 0.0)
+    }
+
+    /// Filters `image`, the resolved pixels of `film`, using its feature
+    /// buffers.
+    pub fn apply(&self, film: &Film, image: &[Color]) -> Vec<Color> {
+        let (width, height) = (film.width, film.height);
+        let albedo: Vec<Color> = (0..width * height)
+            .map(|index| film.albedo(index % width, index / width))
+            .collect();
+        let normal: Vec<Vec3> = (0..width * height)
+            .map(|index| film.normal(index % width, index / width))
+            .collect();
+        let depth: Vec<Option<f64>> = (0..width * height)
+            .map(|index| film.depth(index % width, index / width))
+            .collect();
+        let irradiance: Vec<Color> = image
+            .iter()
+            .zip(&albedo)
+            .map(|(&c, &a)| {
+                Color::new(
+                    c.r / (a.r + ALBEDO_EPSILON),
+                    c.g / (a.g + ALBEDO_EPSILON),
+                    c.b / (a.b + ALBEDO_EPSILON),
+                )
+            })
+            .collect();
+
+        let radius = self.radius as isize;
+        let mut output = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let center = y * width + x;
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                let mut weight_sum = 0.0;
+
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        let (nx, ny) = (x as isize + dx, y as isize + dy);
+                        if nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize {
+                            continue;
+                        }
+                        let neighbor = ny as usize * width + nx as usize;
+
+                        let spatial = (dx * dx + dy * dy) as f64 / self.sigma_spatial.powi(2);
+                        let color = color_distance2(irradiance[center], irradiance[neighbor])
+                            / self.sigma_color.powi(2);
+                        let albedo_term = color_distance2(albedo[center], albedo[neighbor])
+                            / self.sigma_albedo.powi(2);
+                        let normal_term =
+                            (1.0 - Vec3::dot(normal[center], normal[neighbor])) / self.sigma_normal;
+                        let depth_term = match (depth[center], depth[neighbor]) {
+                            (Some(a), Some(b)) => {
+                                ((a - b) / (self.sigma_depth * a.max(1e-3))).powi(2)
+                            }
+                            (None, None) => 0.0,
+                            _ => f64::INFINITY,
+                        };
+
+                        let weight = (-0.5 * (spatial + color + albedo_term + depth_term)
+                            - normal_term.max(0.0))
+                        .exp();
+                        sum = sum + weight * irradiance[neighbor];
+                        weight_sum += weight;
+                    }
+                }
+
+                let filtered = (1.0 / weight_sum) * sum;
+                let a = albedo[center];
+                output.push(Color::new(
+                    filtered.r * (a.r + ALBEDO_EPSILON),
+                    filtered.g * (a.g + ALBEDO_EPSILON),
+                    filtered.b * (a.b + ALBEDO_EPSILON),
+                ));
+            }
+        }
+        output
+    }
+}
+
+fn color_distance2(a: Color, b: Color) -> f64 {
+    (a.r - b.r).powi(2) + (a.g - b.g).powi(2) + (a.b - b.b).powi(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film::SurfaceFeatures;
+
+    const SIZE: usize = 8;
+
+    /// A film whose left and right halves have the given colors and features.
+    fn split_film(left: (Color, SurfaceFeatures), right: (Color, SurfaceFeatures)) -> Film {
+        let mut film = Film::new(SIZE, SIZE);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let (color, features) = if x < SIZE / 2 { left } else { right };
+                film.add_sample(x, y, color, 1.0);
+                film.record_estimate(x, y, color);
+                film.record_features(x, y, &features);
+            }
+        }
+        film
+    }
+
+    fn features(albedo: f64, normal: Vec3) -> SurfaceFeatures {
+        SurfaceFeatures {
+            albedo: Color::new(albedo, albedo, albedo),
+            normal,
+            depth: Some(2.0),
+            ..SurfaceFeatures::background()
+        }
+    }
+
+    /// Denoises `film`, with color differences barely stopping the filter so
+    /// that only the feature buffers can keep edges.
+    fn denoise_ignoring_color(film: &Film) -> Vec<Color> {
+        let denoiser = Denoiser {
+            sigma_color: 1e3,
+            ..Denoiser::default()
+        };
+        denoiser.apply(film, &film.resolve())
+    }
+
+    #[test]
+    fn flat_images_stay_flat() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let color = Color::new(0.3, 0.5, 0.7);
+        let film = split_film((color, features(0.5, up)), (color, features(0.5, up)));
+        for c in Denoiser::default().apply(&film, &film.resolve()) {
+            for (value, expected) in [(c.r, 0.3), (c.g, 0.5), (c.b, 0.7)] {
+                assert!((value - expected).abs() < 1e-12, "{c:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_edges_in_the_normal_buffer() {
+        let (dark, bright) = (Color::new(0.2, 0.2, 0.2), Color::new(0.8, 0.8, 0.8));
+        let film = split_film(
+            (dark, features(0.5, Vec3::new(0.0, 0.0, 1.0))),
+            (bright, features(0.5, Vec3::new(1.0, 0.0, 0.0))),
+        );
+        let denoised = denoise_ignoring_color(&film);
+        for y in 0..SIZE {
+            assert!((denoised[y * SIZE + SIZE / 2 - 1].g - 0.2).abs() < 0.01);
+            assert!((denoised[y * SIZE + SIZE / 2].g - 0.8).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn keeps_edges_in_the_albedo_buffer() {
+        // The same color over different albedos is different lighting.
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let color = Color::new(0.2, 0.2, 0.2);
+        let film = split_film((color, features(0.2, up)), (color, features(0.8, up)));
+        for c in denoise_ignoring_color(&film) {
+            assert!((c.g - 0.2).abs() < 0.01, "{c:?}");
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    io::{self, Read, Write},
+    ops::Range,
+};
+
+use crate::{
+    checkpoint::{read_f64, read_u64, write_f64, write_u64},
+    color::Color,
+    filter::Filter,
+    vec3::Vec3,
+};
+
+/// Surface properties seen by a camera ray at its first hit, used to guide
+/// the denoiser and written out as AOVs.
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceFeatures {
+    pub albedo: Color,
+    pub normal: Vec3,
+    /// Distance from the camera along its viewing axis (from its center for
+    /// fisheye and panoramic cameras), or `None` if the ray escaped.
+    pub depth: Option<f64>,
+    pub position: Vec3,
+    /// Identifiers of the material and object hit; 0 for the background.
+    pub material_id: u32,
+    pub object_id: u32,
+}
+
+impl SurfaceFeatures {
+    pub fn background() -> Self {
+        SurfaceFeatures {
+            albedo: Color::new(0.0, 0.0, 0.0),
+            normal: Vec3::zero(),
+            depth: None,
+            position: Vec3::zero(),
+            material_id: 0,
+            object_id: 0,
+        }
+    }
+}
+
+/// Framebuffer accumulating weighted radiance samples per pixel.
+#[derive(Clone)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    sum: Vec<Color>,
+    weight_sum: Vec<f64>,
+
+    // Running luminance statistics of each pixel's own samples (Welford's
+    // algorithm), used to judge convergence.
+    sample_count: Vec<u32>,
+    mean: Vec<f64>,
+    m2: Vec<f64>,
+
+    // Feature buffers, averaged over each pixel's own samples. Depth and
+    // position are averaged over the samples that hit something, and IDs,
+    // which cannot be averaged, come from the first of those.
+    albedo_sum: Vec<Color>,
+    normal_sum: Vec<Vec3>,
+    depth_sum: Vec<f64>,
+    position_sum: Vec<Vec3>,
+    hit_count: Vec<u32>,
+    material_id: Vec<u32>,
+    object_id: Vec<u32>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Film {
+            width,
+            height,
+            sum: vec![Color::new(0.0, 0.0, 0.0); width * height],
+            weight_sum: vec![0.0; width * height],
+            sample_count: vec![0; width * height],
+            mean: vec![0.0; width * height],
+            m2: vec![0.0; width * height],
+            albedo_sum: vec![Color::new(0.0, 0.0, 0.0); width * height],
+            normal_sum: vec![Vec3::zero(); width * height],
+            depth_sum: vec![0.0; width * height],
+            position_sum: vec![Vec3::zero(); width * height],
+            hit_count: vec![0; width * height],
+            material_id: vec![0; width * height],
+            object_id: vec![0; width * height],
+        }
+    }
+
+    /// Adds the first-hit features of one of pixel (x, y)'s samples. Must be
+    /// called alongside `record_estimate`, whose count averages them.
+    pub fn record_features(&mut self, x: usize, y: usize, features: &SurfaceFeatures) {
+        let index = y * self.width + x;
+        self.albedo_sum[index] = self.albedo_sum[index] + features.albedo;
+        self.normal_sum[index] = self.normal_sum[index] + features.normal;
+        if let Some(depth) = features.depth {
+            if self.hit_count[index] == 0 {
+                self.material_id[index] = features.material_id;
+                self.object_id[index] = features.object_id;
+            }
+            self.depth_sum[index] += depth;
+            self.position_sum[index] = self.position_sum[index] + features.position;
+            self.hit_count[index] += 1;
+        }
+    }
+
+    pub fn albedo(&self, x: usize, y: usize) -> Color {
+        let index = y * self.width + x;
+        let n = self.sample_count[index].max(1) as f64;
+        (1.0 / n) * self.albedo_sum[index]
+    }
+
+    /// Average normal of the pixel, or zero where nothing was hit.
+    pub fn normal(&self, x: usize, y: usize) -> Vec3 {
+        let normal = self.normal_sum[y * self.width + x];
+        if normal.near_zero() {
+            return Vec3::zero();
+        }
+        Vec3::unit_vector(normal)
+    }
+
+    pub fn depth(&self, x: usize, y: usize) -> Option<f64> {
+        let index = y * self.width + x;
+        (self.hit_count[index] > 0).then(|| self.depth_sum[index] / self.hit_count[index] as f64)
+    }
+
+    pub fn position(&self, x: usize, y: usize) -> Option<Vec3> {
+        let index = y * self.width + x;
+        (self.hit_count[index] > 0).then(|| self.position_sum[index] / self.hit_count[index] as f64)
+    }
+
+    pub fn material_id(&self, x: usize, y: usize) -> u32 {
+        self.material_id[y * self.width + x]
+    }
+
+    pub fn object_id(&self, x: usize, y: usize) -> u32 {
+        self.object_id[y * self.width + x]
+    }
+
+    /// Updates the convergence statistics of pixel (x, y) with one of its
+    /// samples.
+    pub fn record_estimate(&mut self, x: usize, y: usize, color: Color) {
+        let index = y * self.width + x;
+        let value = color.luminance();
+        self.sample_count[index] += 1;
+        let delta = value - self.mean[index];
+        self.mean[index] += delta / self.sample_count[index] as f64;
+        self.m2[index] += delta * (value - self.mean[index]);
+    }
+
+    pub fn sample_count(&self, x: usize, y: usize) -> u32 {
+        self.sample_count[y * self.width + x]
+    }
+
+    /// Standard error of the pixel's mean luminance relative to the mean. Very
+    /// dark pixels are measured against a floor so they can converge.
+    pub fn relative_error(&self, x: usize, y: usize) -> f64 {
+        let index = y * self.width + x;
+        let n = self.sample_count[index] as f64;
+        if n < 2.0 {
+            return f64::INFINITY;
+        }
+        let variance = self.m2[index] / (n - 1.0);
+        (variance / n).sqrt() / self.mean[index].max(0.01)
+    }
+
+    pub fn add_sample(&mut self, x: usize, y: usize, color: Color, weight: f64) {
+        let index = y * self.width + x;
+        self.sum[index] = self.sum[index] + weight * color;
+        self.weight_sum[index] += weight;
+    }
+
+    /// Adds an importance-sampled estimate whose weight averages to one. The
+    /// pixel is normalized by the number of estimates rather than by their
+    /// weights, which can cancel out where a filter has negative lobes.
+    pub fn add_estimate(&mut self, x: usize, y: usize, color: Color, weight: f64) {
+        let index = y * self.width + x;
+        self.sum[index] = self.sum[index] + weight * color;
+        self.weight_sum[index] += 1.0;
+    }
+
+    /// Adds a sample taken `offset` pixels from the center of `pixel` to every
+    /// pixel in the given columns and rows whose center lies within the
+    /// filter's radius. Weights depend on pixel positions only through their
+    /// differences, so they come out the same wherever the film lies in the
+    /// image.
+    pub fn splat_within(
+        &mut self,
+        pixel: (usize, usize),
+        offset: (f64, f64),
+        color: Color,
+        filter: &Filter,
+        columns: Range<usize>,
+        rows: Range<usize>,
+    ) {
+        let radius = filter.radius();
+        let (x, y) = (pixel.0 as isize, pixel.1 as isize);
+        let x0 = (x + (offset.0 - radius).ceil() as isize).max(columns.start as isize);
+        let y0 = (y + (offset.1 - radius).ceil() as isize).max(rows.start as isize);
+        let x1 = (x + (offset.0 + radius).floor() as isize)
+            .min(columns.end.min(self.width) as isize - 1);
+        let y1 =
+            (y + (offset.1 + radius).floor() as isize).min(rows.end.min(self.height) as isize - 1);
+
+        for py in y0..=y1 {
+            for px in x0..=x1 {
+                let weight =
+                    filter.evaluate((px - x) as f64 - offset.0, (py - y) as f64 - offset.1);
+                if weight != 0.0 {
+                    self.add_sample(px as usize, py as usize, color, weight);
+                }
+            }
+        }
+    }
+
+    /// Reconstructed values of all pixels, row by row.
+    pub fn resolve(&self) -> Vec<Color> {
+        (0..self.width * self.height)
+            .map(|index| self.pixel(index % self.width, index / self.width))
+            .collect()
+    }
+
+    /// Reconstructed value of a pixel, or black if nothing reached it.
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        let index = y * self.width + x;
+        if self.weight_sum[index] == 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        (1.0 / self.weight_sum[index]) * self.sum[index]
+    }
+
+    /// Copy of the `width` x `height` pixel block whose upper-left pixel is
+    /// (x, y).
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Film {
+        let indices: Vec<usize> = (y..y + height)
+            .flat_map(|row| (x..x + width).map(move |column| row * self.width + column))
+            .collect();
+        fn pick<T: Copy>(buffer: &[T], indices: &[usize]) -> Vec<T> {
+            indices.iter().map(|&index| buffer[index]).collect()
+        }
+        Film {
+            width,
+            height,
+            sum: pick(&self.sum, &indices),
+            weight_sum: pick(&self.weight_sum, &indices),
+            sample_count: pick(&self.sample_count, &indices),
+            mean: pick(&self.mean, &indices),
+            m2: pick(&self.m2, &indices),
+            albedo_sum: pick(&self.albedo_sum, &indices),
+            normal_sum: pick(&self.normal_sum, &indices),
+            depth_sum: pick(&self.depth_sum, &indices),
+            position_sum: pick(&self.position_sum, &indices),
+            hit_count: pick(&self.hit_count, &indices),
+            material_id: pick(&self.material_id, &indices),
+            object_id: pick(&self.object_id, &indices),
+        }
+    }
+
+    /// Serializes the accumulated samples and statistics for a checkpoint.
+    pub(crate) fn write_state(&self, out: &mut impl Write) -> io::Result<()> {
+        for index in 0..self.width * self.height {
+            let sum = self.sum[index];
+            for value in [sum.r, sum.g, sum.b, self.weight_sum[index]] {
+                write_f64(out, value)?;
+            }
+            write_u64(out, self.sample_count[index] as u64)?;
+            write_f64(out, self.mean[index])?;
+            write_f64(out, self.m2[index])?;
+
+            let (albedo, normal) = (self.albedo_sum[index], self.normal_sum[index]);
+            for value in [albedo.r, albedo.g, albedo.b, normal.x, normal.y, normal.z] {
+                write_f64(out, value)?;
+            }
+            write_f64(out, self.depth_sum[index])?;
+            let position = self.position_sum[index];
+            for value in [position.x, position.y, position.z] {
+                write_f64(out, value)?;
+            }
+            write_u64(out, self.hit_count[index] as u64)?;
+            write_u64(out, self.material_id[index] as u64)?;
+            write_u64(out, self.object_id[index] as u64)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read_state(
+        input: &mut impl Read,
+        width: usize,
+        height: usize,
+    ) -> io::Result<Film> {
+        let mut film = Film::new(width, height);
+        for index in 0..width * height {
+            film.sum[index] = Color::new(read_f64(input)?, read_f64(input)?, read_f64(input)?);
+            film.weight_sum[index] = read_f64(input)?;
+            film.sample_count[index] = read_u64(input)? as u32;
+            film.mean[index] = read_f64(input)?;
+            film.m2[index] = read_f64(input)?;
+
+            film.albedo_sum[index] =
+                Color::new(read_f64(input)?, read_f64(input)?, read_f64(input)?);
+            film.normal_sum[index] =
+                Vec3::new(read_f64(input)?, read_f64(input)?, read_f64(input)?);
+            film.depth_sum[index] = read_f64(input)?;
+            film.position_sum[index] =
+                Vec3::new(read_f64(input)?, read_f64(input)?, read_f64(input)?);
+            film.hit_count[index] = read_u64(input)? as u32;
+            film.material_id[index] = read_u64(input)? as u32;
+            film.object_id[index] = read_u64(input)? as u32;
+        }
+        Ok(film)
+    }
+
+    /// Writes the number of samples per pixel as a PPM, from black (none)
+    /// through blue and red to yellow (`max_samples`).
+    pub fn write_sample_heatmap(&self, out: &mut impl Write, max_samples: usize) -> io::Result<()> {
+        writeln!(out, "P3\n {} {}\n255", self.width, self.height)?;
+        for &count in &self.sample_count {
+            let t = (count as f64 / max_samples.max(1) as f64).clamp(0.0, 1.0);
+            let r = (3.0 * t - 1.0).clamp(0.0, 1.0);
+            let g = (3.0 * t - 2.0).clamp(0.0, 1.0);
+            let b = (1.0 - (3.0 * t - 1.0).abs()).clamp(0.0, 1.0);
+            let [r, g, b] = [r, g, b].map(|c| (255.0 * c) as u8);
+            writeln!(out, "{r} {g} {b}")?;
+        }
+        Ok(())
+    }
+}
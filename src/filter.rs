@@ -0,0 +1,235 @@
+use std::f64::consts::PI;
+
+/// Pixel reconstruction filter. All filters are separable, `f(x, y) = f(x) f(y)`,
+/// and vanish beyond `radius` pixels from the pixel center.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Box {
+        radius: f64,
+    },
+    Tent {
+        radius: f64,
+    },
+    Gaussian {
+        radius: f64,
+        sigma: f64,
+    },
+    /// Mitchell–Netravali cubic; `b = c = 1/3` is the recommended default.
+    Mitchell {
+        radius: f64,
+        b: f64,
+        c: f64,
+    },
+    /// Sinc windowed by a wider sinc with `tau` lobes.
+    Lanczos {
+        radius: f64,
+        tau: f64,
+    },
+}
+
+/// How samples reach the framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterMode {
+    /// Distribute sample positions around the pixel center according to the
+    /// filter, so every sample only contributes to its own pixel.
+    ImportanceSampling,
+    /// Sample uniformly inside the pixel and add each sample to every pixel
+    /// within the filter radius, weighted by the filter.
+    Splatting,
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius, .. } => radius,
+        }
+    }
+
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let radius = self.radius();
+        if x.abs() > radius {
+            return 0.0;
+        }
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { radius } => radius - x.abs(),
+            Filter::Gaussian { radius, sigma } => {
+                // Shifted down so the filter reaches zero at its radius.
+                (gaussian(x, sigma) - gaussian(radius, sigma)).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => mitchell(2.0 * x / radius, b, c),
+            Filter::Lanczos { tau, .. } => sinc(x) * sinc(x / tau),
+        }
+    }
+}
+
+fn gaussian(x: f64, sigma: f64) -> f64 {
+    (-x * x / (2.0 * sigma * sigma)).exp() / (2.0 * PI * sigma * sigma).sqrt()
+}
+
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    let x = x.abs();
+    if x <= 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x <= 2.0 {
+        ((-b - 6.0 * c) * x.powi(3)
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+/// Tabulated distribution proportional to `|f(x)|` of a filter, used to place
+/// samples by filter importance sampling.
+pub struct FilterSampler {
+    filter: Filter,
+    cdf: Vec<f64>,
+    // Integral of |f| over the signed integral of f, both over the 2D
+    // support: the magnitude of each sample weight.
+    weight: f64,
+}
+
+impl FilterSampler {
+    const BINS_PER_PIXEL: f64 = 64.0;
+
+    pub fn new(filter: Filter) -> Self {
+        let radius = filter.radius();
+        let bins = (2.0 * radius * Self::BINS_PER_PIXEL).ceil().max(1.0) as usize;
+        let bin_width = 2.0 * radius / bins as f64;
+
+        let mut cdf = Vec::with_capacity(bins + 1);
+        cdf.push(0.0);
+        let mut signed_integral = 0.0;
+        for b in 0..bins {
+            let x = -radius + (b as f64 + 0.5) * bin_width;
+            let value = filter.evaluate_1d(x) * bin_width;
+            cdf.push(cdf[b] + value.abs());
+            signed_integral += value;
+        }
+        let integral = cdf[bins];
+        for c in cdf.iter_mut() {
+            *c /= integral;
+        }
+
+        FilterSampler {
+            filter,
+            cdf,
+            weight: (integral * integral) / (signed_integral * signed_integral),
+        }
+    }
+
+    /// Maps a uniform sample to an offset from the pixel center and the weight
+    /// of the sample, `f / pdf` over the integral of the filter. Weights are
+    /// negative in negative lobes and average to one.
+    pub fn sample(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+        let x = self.sample_1d(u.0);
+        let y = self.sample_1d(u.1);
+        let f = self.filter.evaluate(x, y);
+        let weight = if f < 0.0 { -self.weight } else { self.weight };
+        ((x, y), weight)
+    }
+
+    fn sample_1d(&self, u: f64) -> f64 {
+        let radius = self.filter.radius();
+        let bins = self.cdf.len() - 1;
+        let b = self.cdf.partition_point(|&c| c <= u).clamp(1, bins) - 1;
+        let width = self.cdf[b + 1] - self.cdf[b];
+        let t = if width > 0.0 {
+            (u - self.cdf[b]) / width
+        } else {
+            0.5
+        };
+        -radius + (b as f64 + t) * 2.0 * radius / bins as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        film::Film,
+        sampler::{IndependentSampler, Sampler},
+    };
+
+    /// Mean and extremes of a flat white image importance sampled through
+    /// `filter` with `samples` samples per pixel.
+    fn flat_image(filter: Filter, samples: usize) -> (f64, f64, f64) {
+        let size = 64;
+        let filter_sampler = FilterSampler::new(filter);
+        let mut sampler = IndependentSampler::new(3);
+        let mut film = Film::new(size, size);
+        let white = Color::new(1.0, 1.0, 1.0);
+        for y in 0..size {
+            for x in 0..size {
+                for i in 0..samples {
+                    sampler.start_pixel_sample((x, y), i);
+                    let (_, weight) = filter_sampler.sample(sampler.get_2d());
+                    film.add_estimate(x, y, white, weight);
+                }
+            }
+        }
+        let values: Vec<f64> = film.resolve().iter().map(|c| c.g).collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        (mean, min, max)
+    }
+
+    #[test]
+    fn box_weights_are_one() {
+        let sampler = FilterSampler::new(Filter::Box { radius: 0.5 });
+        for u in [(0.0, 0.0), (0.3, 0.9), (0.99, 0.5)] {
+            assert_eq!(sampler.sample(u).1, 1.0);
+        }
+    }
+
+    #[test]
+    fn flat_images_stay_flat_under_negative_lobes() {
+        for (filter, samples) in [
+            (
+                Filter::Mitchell {
+                    radius: 2.0,
+                    b: 1.0 / 3.0,
+                    c: 1.0 / 3.0,
+                },
+                2,
+            ),
+            (
+                Filter::Lanczos {
+                    radius: 3.0,
+                    tau: 3.0,
+                },
+                16,
+            ),
+        ] {
+            // Each pixel averages weights of the same magnitude, so it can
+            // only be noisy, never blow up, and is white on average.
+            let weight = FilterSampler::new(filter).weight;
+            let (mean, min, max) = flat_image(filter, samples);
+            assert!((mean - 1.0).abs() < 0.01, "{filter:?}: mean {mean}");
+            assert!(min >= -weight && max <= weight, "{filter:?}: {min}..{max}");
+        }
+    }
+}
@@ -2,15 +2,30 @@ use crate::{
     interval::Interval,
     material::Material,
     ray::Ray,
+    util::{hash_to_f64, hash_u64s},
     vec3::{Point3, Vec3},
 };
 
+#[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     pub p: Point3,
     pub normal: Vec3,
     pub t: f64,
     pub front_face: bool,
     pub material: &'a dyn Material,
+
+    // Surface parameterization, used for texture lookups.
+    pub u: f64,
+    pub v: f64,
+    // Directions of increasing u and v on the surface. Together with the
+    // normal they form the tangent space that normal maps are expressed in.
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+
+    // Labels for AOV output. Object IDs are assigned by `HittableList`,
+    // material IDs by the primitive; 0 means unassigned.
+    pub object_id: u32,
+    pub material_id: u32,
 }
 
 impl<'a> HitRecord<'a> {
@@ -21,17 +36,99 @@ impl<'a> HitRecord<'a> {
             t,
             front_face: true,
             material,
+            u: 0.0,
+            v: 0.0,
+            tangent: Vec3::zero(),
+            bitangent: Vec3::zero(),
+            object_id: 0,
+            material_id: 0,
+        }
+    }
+
+    pub fn set_uv(&mut self, u: f64, v: f64, tangent: Vec3, bitangent: Vec3) {
+        self.u = u;
+        self.v = v;
+        self.tangent = tangent;
+        self.bitangent = bitangent;
+    }
+    /// Copy of this record shaded with `normal` instead of the geometric
+    /// normal. Perturbations that would flip the normal below the surface are
+    /// ignored.
+    pub fn with_shading_normal(&self, normal: Vec3) -> HitRecord<'a> {
+        let mut rec = *self;
+        if !normal.near_zero() && Vec3::dot(normal, self.normal) > 0.0 {
+            rec.normal = Vec3::unit_vector(normal);
         }
+        rec
     }
+
+    /// Whether the surface blocks `ray` at this hit. Partially transparent
+    /// surfaces are resolved stochastically against their opacity, so cut-out
+    /// regions let every ray through, primary and secondary alike. The random
+    /// number is a hash of the render seed, the ray and the hit point, which
+    /// keeps renders deterministic.
+    pub fn is_opaque(&self, ray: &Ray) -> bool {
+        let opacity = self.material.opacity(self);
+        if opacity >= 1.0 {
+            return true;
+        }
+        if opacity <= 0.0 {
+            return false;
+        }
+        let hash = hash_u64s(&[
+            ray.seed,
+            ray.origin.x.to_bits(),
+            ray.origin.y.to_bits(),
+            ray.origin.z.to_bits(),
+            ray.direction.x.to_bits(),
+            ray.direction.y.to_bits(),
+            ray.direction.z.to_bits(),
+            self.t.to_bits(),
+        ]);
+        hash_to_f64(hash) < opacity
+    }
+
     pub fn set_normal(&mut self, ray: &Ray) {
         self.front_face = Vec3::dot(self.normal, ray.direction) < 0.0;
 
         if !self.front_face {
+            // Flipping the bitangent along with the normal keeps the tangent
+            // frame right-handed, so normal maps are not mirrored inside.
             self.normal = -self.normal;
+            self.bitangent = -self.bitangent;
         }
     }
 }
 
 pub trait Hittable {
-    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        material::{AlphaMask, Lambertian},
+        texture::SolidColor,
+    };
+
+    #[test]
+    fn alpha_test_depends_on_the_seed() {
+        let material = AlphaMask::new(
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+            SolidColor::new(Color::new(0.5, 0.5, 0.5)),
+        );
+        let rec = HitRecord::new(Point3::zero(), Vec3::new(0.0, 0.0, 1.0), 1.0, &material);
+        let differing = (0..64)
+            .filter(|&i| {
+                let ray = |seed| {
+                    Ray::new(Point3::new(i as f64, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0))
+                        .with_seed(seed)
+                };
+                rec.is_opaque(&ray(1)) != rec.is_opaque(&ray(2))
+            })
+            .count();
+        assert!(differing > 0);
+    }
 }
@@ -11,13 +11,18 @@ impl<'a> HittableList<'a> {
 }
 
 impl<'a> Hittable for HittableList<'a> {
-    fn hit(&self, ray: &crate::ray::Ray, ray_t: Interval) -> Option<crate::hittable::HitRecord> {
+    fn hit(
+        &self,
+        ray: &crate::ray::Ray,
+        ray_t: Interval,
+    ) -> Option<crate::hittable::HitRecord<'_>> {
         let mut rec = None;
         let mut closest_so_far = ray_t.max;
 
-        for object in self.objects.iter() {
-            if let Some(hrec) = object.hit(ray, Interval::new(ray_t.min, closest_so_far)) {
+        for (index, object) in self.objects.iter().enumerate() {
+            if let Some(mut hrec) = object.hit(ray, Interval::new(ray_t.min, closest_so_far)) {
                 closest_so_far = hrec.t;
+                hrec.object_id = index as u32 + 1;
                 rec = Some(hrec);
             }
         }
@@ -0,0 +1,365 @@
+use std::{fs, io, path::Path};
+
+use crate::vec3::Vec3;
+
+/// One refracting surface of a lens, or the aperture stop if `radius` is 0.
+/// Lengths are in millimeters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LensElement {
+    /// Radius of curvature; positive when the surface bulges towards the
+    /// scene.
+    pub radius: f64,
+    /// Distance along the axis to the next surface, or to the film for the
+    /// last one.
+    pub thickness: f64,
+    /// Refractive index of the medium behind the surface, towards the film.
+    /// 0 stands for air.
+    pub ior: f64,
+    /// Diameter of the surface's clear aperture.
+    pub aperture: f64,
+}
+
+/// A lens prescription, listed from the front element to the one closest to
+/// the film, traced ray by ray. The film sits at `z = 0` in lens space and
+/// the scene lies towards negative `z`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LensSystem {
+    pub elements: Vec<LensElement>,
+    /// Diagonal of the film, in millimeters.
+    pub film_diagonal: f64,
+    /// Size of a millimeter in scene units.
+    pub scene_units_per_mm: f64,
+}
+
+impl LensSystem {
+    pub fn new(elements: Vec<LensElement>) -> Self {
+        LensSystem {
+            elements,
+            film_diagonal: 35.0,
+            scene_units_per_mm: 0.001,
+        }
+    }
+
+    /// Loads a lens description with one `radius thickness ior aperture` line
+    /// per surface, as used by pbrt. Lines starting with `#` are comments.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let mut elements = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values: Vec<f64> = line
+                .split_whitespace()
+                .map(|t| t.parse().map_err(|_| invalid("malformed lens element")))
+                .collect::<io::Result<_>>()?;
+            let [radius, thickness, ior, aperture] = values[..] else {
+                return Err(invalid("lens elements need four values"));
+            };
+            elements.push(LensElement {
+                radius,
+                thickness,
+                ior,
+                aperture,
+            });
+        }
+        if elements.is_empty() {
+            return Err(invalid("lens has no elements"));
+        }
+        Ok(LensSystem::new(elements))
+    }
+
+    /// Whether the lens has elements, each with finite settings and a positive
+    /// aperture and thickness, and a positive film diagonal and scale.
+    pub(crate) fn is_valid(&self) -> bool {
+        !self.elements.is_empty()
+            && self.elements.iter().all(|e| {
+                e.radius.is_finite()
+                    && e.ior.is_finite()
+                    && e.ior >= 0.0
+                    && [e.thickness, e.aperture]
+                        .iter()
+                        .all(|&v| v.is_finite() && v > 0.0)
+            })
+            && [self.film_diagonal, self.scene_units_per_mm]
+                .iter()
+                .all(|&v| v.is_finite() && v > 0.0)
+    }
+
+    /// Width and height of the film for an image with the given aspect ratio.
+    pub fn film_size(&self, aspect_ratio: f64) -> (f64, f64) {
+        let height = self.film_diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        (aspect_ratio * height, height)
+    }
+
+    /// Copy of the lens with the film moved so that objects `distance` scene
+    /// units in front of the film are sharp, using the thick lens
+    /// approximation. `None` if the lens does not converge light, blocks the
+    /// rays near its axis, or cannot focus that close.
+    pub fn focused(&self, distance: f64) -> Option<LensSystem> {
+        let mut lens = self.clone();
+        let h = 0.001 * self.film_diagonal;
+
+        // Parallel rays entering from either side cross the axis at the focal
+        // points, and appear to bend at the principal planes.
+        let (o, d) = self.trace_from_film(Vec3::new(h, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0))?;
+        let scene_principal = o.z + (h - o.x) / d.x * d.z;
+        let front = -self.elements.iter().map(|e| e.thickness).sum::<f64>();
+        let (o, d) =
+            self.trace_from_scene(Vec3::new(h, 0.0, front - 1.0), Vec3::new(0.0, 0.0, 1.0))?;
+        let film_focal_point = o.z - o.x / d.x * d.z;
+        let film_principal = o.z + (h - o.x) / d.x * d.z;
+        let focal_length = film_focal_point - film_principal;
+        if !(focal_length.is_finite() && focal_length > 0.0 && scene_principal.is_finite()) {
+            return None;
+        }
+
+        // Moving the lens away from the film by `delta` must satisfy
+        // 1/s + 1/s' = 1/f, where the object and image distances s and s'
+        // always sum to `span`.
+        let object_distance = distance / self.scene_units_per_mm;
+        let span = scene_principal + object_distance - film_principal;
+        let discriminant = span * span - 4.0 * focal_length * span;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let image_distance = 0.5 * (span - discriminant.sqrt());
+        let delta = image_distance + film_principal;
+        let last = lens.elements.last_mut()?;
+        last.thickness += delta;
+        (last.thickness > 0.0).then_some(lens)
+    }
+
+    /// Ray leaving the lens towards the scene from `film_point`, aimed at the
+    /// point of the rear element given by `u`, with origin and direction in
+    /// lens space scaled to scene units. `None` if the lens blocks it.
+    pub fn generate_ray(&self, film_point: (f64, f64), u: Vec3) -> Option<(Vec3, Vec3)> {
+        let rear = self.elements.last()?;
+        let rear_radius = rear.aperture / 2.0;
+        let origin = Vec3::new(film_point.0, film_point.1, 0.0);
+        let target = Vec3::new(rear_radius * u.x, rear_radius * u.y, -rear.thickness);
+        let (o, d) = self.trace_from_film(origin, target - origin)?;
+        Some((self.scene_units_per_mm * o, d))
+    }
+
+    fn trace_from_film(&self, mut o: Vec3, d: Vec3) -> Option<(Vec3, Vec3)> {
+        let mut d = Vec3::unit_vector(d);
+        let mut z = 0.0;
+        for (i, element) in self.elements.iter().enumerate().rev() {
+            z -= element.thickness;
+            let eta_i = medium_ior(element.ior);
+            let eta_t = if i > 0 {
+                medium_ior(self.elements[i - 1].ior)
+            } else {
+                1.0
+            };
+            (o, d) = refract_at(element, z, o, d, eta_i / eta_t)?;
+        }
+        Some((o, d))
+    }
+
+    fn trace_from_scene(&self, mut o: Vec3, d: Vec3) -> Option<(Vec3, Vec3)> {
+        let mut d = Vec3::unit_vector(d);
+        let mut z = -self.elements.iter().map(|e| e.thickness).sum::<f64>();
+        for (i, element) in self.elements.iter().enumerate() {
+            let eta_i = if i > 0 {
+                medium_ior(self.elements[i - 1].ior)
+            } else {
+                1.0
+            };
+            let eta_t = medium_ior(element.ior);
+            (o, d) = refract_at(element, z, o, d, eta_i / eta_t)?;
+            z += element.thickness;
+        }
+        Some((o, d))
+    }
+}
+
+fn medium_ior(ior: f64) -> f64 {
+    if ior == 0.0 { 1.0 } else { ior }
+}
+
+/// Carries a ray with unit direction `d` across the surface of `element`
+/// whose vertex is at `z`. `eta` is the ratio of refractive indices before
+/// and after the surface.
+fn refract_at(element: &LensElement, z: f64, o: Vec3, d: Vec3, eta: f64) -> Option<(Vec3, Vec3)> {
+    let (t, normal) = if element.radius == 0.0 {
+        ((z - o.z) / d.z, None)
+    } else {
+        let center = Vec3::new(0.0, 0.0, z + element.radius);
+        let oc = o - center;
+        let b = Vec3::dot(oc, d);
+        let c = Vec3::dot(oc, oc) - element.radius * element.radius;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        // The surface is the half of the sphere around its vertex.
+        let root = discriminant.sqrt();
+        let t = if (d.z > 0.0) ^ (element.radius < 0.0) {
+            -b - root
+        } else {
+            -b + root
+        };
+        let p = o + t * d;
+        (t, Some(Vec3::unit_vector(p - center)))
+    };
+    if t <= 0.0 {
+        return None;
+    }
+
+    let p = o + t * d;
+    let half_aperture = element.aperture / 2.0;
+    if p.x * p.x + p.y * p.y > half_aperture * half_aperture {
+        return None;
+    }
+    let Some(mut n) = normal else {
+        return Some((p, d));
+    };
+    if Vec3::dot(n, d) > 0.0 {
+        n = -n;
+    }
+
+    let cos_i = -Vec3::dot(n, d);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    if sin2_t > 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some((p, eta * d + (eta * cos_i - cos_t) * n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(radius: f64, thickness: f64, ior: f64, aperture: f64) -> LensElement {
+        LensElement {
+            radius,
+            thickness,
+            ior,
+            aperture,
+        }
+    }
+
+    /// A thin biconvex lens with a focal length of 100 mm.
+    fn thin_lens() -> LensSystem {
+        LensSystem::new(vec![
+            element(100.0, 0.1, 1.5, 2.0),
+            element(-100.0, 100.0, 0.0, 2.0),
+        ])
+    }
+
+    #[test]
+    fn refract_at_follows_snells_law() {
+        let surface = element(100.0, 1.0, 1.5, 40.0);
+        let d = Vec3::new(0.0, 0.0, 1.0);
+        let (p, t) = refract_at(&surface, 0.0, Vec3::new(10.0, 0.0, -5.0), d, 1.0 / 1.5).unwrap();
+
+        let n = Vec3::unit_vector(p - Vec3::new(0.0, 0.0, 100.0));
+        assert!((p.x * p.x + (p.z - 100.0).powi(2) - 100.0 * 100.0).abs() < 1e-9);
+        let sin_i = Vec3::length(Vec3::cross(n, d));
+        let sin_t = Vec3::length(Vec3::cross(n, t));
+        assert!((sin_i - 1.5 * sin_t).abs() < 1e-12);
+        assert!((Vec3::length(t) - 1.0).abs() < 1e-12);
+        // A converging surface bends the ray towards the axis.
+        assert!(t.x < 0.0);
+    }
+
+    #[test]
+    fn refract_at_blocks_rays_outside_the_aperture() {
+        let stop = element(0.0, 1.0, 0.0, 4.0);
+        let d = Vec3::new(0.0, 0.0, 1.0);
+        let (p, t) = refract_at(&stop, 0.0, Vec3::new(1.0, 1.0, -5.0), d, 1.0).unwrap();
+        assert_eq!((p.x, p.y, p.z), (1.0, 1.0, 0.0));
+        assert_eq!((t.x, t.y, t.z), (d.x, d.y, d.z));
+        assert!(refract_at(&stop, 0.0, Vec3::new(2.0, 1.0, -5.0), d, 1.0).is_none());
+    }
+
+    #[test]
+    fn refract_at_reflects_grazing_rays_totally() {
+        let surface = element(10.0, 1.0, 0.0, 20.0);
+        let d = Vec3::unit_vector(Vec3::new(1.0, 0.0, 1.0));
+        assert!(refract_at(&surface, 0.0, Vec3::new(-1.0, 0.0, -5.0), d, 1.5).is_none());
+        assert!(refract_at(&surface, 0.0, Vec3::new(-1.0, 0.0, -5.0), d, 1.0 / 1.5).is_some());
+    }
+
+    #[test]
+    fn focused_matches_the_thin_lens_equation() {
+        // 1 / s + 1 / s' = 1 / f with s + s' = 1000 mm.
+        let lens = thin_lens().focused(1.0).unwrap();
+        let image_distance = 0.5 * (1000.0 - (1000.0f64 * 1000.0 - 4.0 * 100.0 * 1000.0).sqrt());
+        let back = lens.elements[1].thickness;
+        assert!(
+            (back - image_distance).abs() < 0.1,
+            "{back} vs {image_distance}"
+        );
+
+        // Paraxial rays from the focused point meet on the film.
+        let front = -lens.elements.iter().map(|e| e.thickness).sum::<f64>();
+        let object = Vec3::new(0.0, 0.0, -1000.0);
+        for h in [0.1, 0.3, 0.5] {
+            let target = Vec3::new(h, 0.0, front);
+            let (o, d) = lens.trace_from_scene(object, target - object).unwrap();
+            let x = o.x - o.z / d.z * d.x;
+            assert!(x.abs() < 1e-3, "ray at height {h} lands at {x}");
+        }
+    }
+
+    #[test]
+    fn focused_fails_when_the_lens_cannot_focus() {
+        // Closer than four focal lengths, no real image reaches the film.
+        assert!(thin_lens().focused(0.3).is_none());
+        let diverging = LensSystem::new(vec![
+            element(-100.0, 0.1, 1.5, 2.0),
+            element(100.0, 100.0, 0.0, 2.0),
+        ]);
+        assert!(diverging.focused(1.0).is_none());
+    }
+
+    #[test]
+    fn generate_ray_is_blocked_by_the_aperture_stop() {
+        let with_stop = |stop_aperture| {
+            LensSystem::new(vec![
+                element(100.0, 2.0, 1.5, 40.0),
+                element(-100.0, 5.0, 0.0, 40.0),
+                element(0.0, 5.0, 0.0, stop_aperture),
+                element(100.0, 2.0, 1.5, 20.0),
+                element(-100.0, 50.0, 0.0, 20.0),
+            ])
+        };
+        let edge = Vec3::new(0.9, 0.0, 0.0);
+        let center = Vec3::new(0.0, 0.0, 0.0);
+        assert!(with_stop(30.0).generate_ray((0.0, 0.0), edge).is_some());
+        assert!(with_stop(2.0).generate_ray((0.0, 0.0), edge).is_none());
+        assert!(with_stop(2.0).generate_ray((0.0, 0.0), center).is_some());
+    }
+
+    #[test]
+    fn load_parses_elements_and_rejects_malformed_files() {
+        let path = std::env::temp_dir().join(format!("ray_tracer_{}.lens", std::process::id()));
+        let load = |text: &str| {
+            fs::write(&path, text).unwrap();
+            LensSystem::load(&path)
+        };
+
+        let lens = load("# radius thickness ior aperture\n\n100 2 1.5 20\n0 5 0 8\n").unwrap();
+        assert_eq!(
+            lens.elements,
+            vec![element(100.0, 2.0, 1.5, 20.0), element(0.0, 5.0, 0.0, 8.0)]
+        );
+        for text in [
+            "100 2 1.5 twenty\n",
+            "100 2 1.5\n",
+            "100 2 1.5 20 1\n",
+            "# none\n",
+        ] {
+            let error = load(text).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{text:?}");
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
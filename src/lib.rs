@@ -0,0 +1,22 @@
+pub mod animation;
+pub mod aov;
+pub mod aperture;
+pub mod camera;
+pub mod checkpoint;
+pub mod color;
+pub mod denoise;
+pub mod film;
+pub mod filter;
+pub mod hittable;
+pub mod hittable_list;
+pub mod interval;
+pub mod lens;
+pub mod material;
+pub mod onb;
+pub mod ray;
+pub mod sampler;
+pub mod sphere;
+pub mod texture;
+pub mod tonemap;
+pub mod util;
+pub mod vec3;
@@ -1,23 +1,15 @@
-use crate::camera::Camera;
-use crate::color::Color;
-use crate::hittable_list::HittableList;
-use crate::material::{Dialectric, Lambertian, Metal};
-use crate::sphere::Sphere;
-use crate::util::{random_f64, random_f64_range};
-use crate::vec3::{Point3, Vec3};
+use ray_tracer::camera::Camera;
+use ray_tracer::color::Color;
+use ray_tracer::hittable_list::HittableList;
+use ray_tracer::material::{Dialectric, Lambertian, Metal};
+use ray_tracer::sphere::Sphere;
+use ray_tracer::util::{random_f64, random_f64_range, seed_random};
+use ray_tracer::vec3::{Point3, Vec3};
 
-mod camera;
-mod color;
-mod hittable;
-mod hittable_list;
-mod interval;
-mod material;
-mod ray;
-mod sphere;
-mod util;
-mod vec3;
+fn main() -> std::io::Result<()> {
+    let seed = 42;
+    seed_random(seed);
 
-fn main() {
     let mut world: HittableList = HittableList {
         objects: Vec::new(),
     };
@@ -65,17 +57,18 @@ fn main() {
     let material_3 = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
     world.add(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, material_3));
 
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = 400;
-    cam.sample_per_pixel = 500;
-    cam.max_depth = 50;
+    let mut cam = Camera::builder()
+        .aspect_ratio(16.0 / 9.0)
+        .image_width(400)
+        .sample_per_pixel(500)
+        .max_depth(50)
+        .seed(seed)
+        .vfov(20.0)
+        .look_from(Point3::new(13.0, 2.0, 3.0))
+        .look_at(Point3::new(0.0, 1.0, 0.0))
+        .defocus_angle(0.6)
+        .focus_dist(10.0)
+        .build()?;
 
-    cam.vfov = 20.0;
-    cam.look_from = Point3::new(13.0, 2.0, 3.0);
-    cam.look_at = Point3::new(0.0, 1.0, 0.0);
-    cam.defocus_angle = 0.6;
-    cam.focus_dist = 10.0;
-
-    cam.render(&world);
+    cam.render(&world)
 }
@@ -1,37 +1,405 @@
-use crate::{color::Color, hittable::HitRecord, ray::Ray, util::random_f64, vec3::Vec3};
+use std::f64::consts::PI;
+
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    onb::Onb,
+    ray::Ray,
+    sampler::{Sampler, sample_cosine_hemisphere, sample_unit_sphere},
+    texture::Texture,
+    vec3::Vec3,
+};
 
 pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)>;
+
+    /// Coverage of the surface at a hit, from 0 (fully cut out) to 1 (solid).
+    fn opacity(&self, _rec: &HitRecord) -> f64 {
+        1.0
+    }
+
+    /// Characteristic surface color at a hit, independent of lighting. Used
+    /// as a guide for denoising.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+}
+
+/// Cuts holes in a material using an opacity texture (the average of its
+/// channels), for leaves, fences and decals.
+pub struct AlphaMask<M, T> {
+    material: M,
+    mask: T,
+}
+
+impl<M: Material, T: Texture> AlphaMask<M, T> {
+    pub fn new(material: M, mask: T) -> Self {
+        AlphaMask { material, mask }
+    }
+}
+
+impl<M: Material, T: Texture> Material for AlphaMask<M, T> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        self.material.scatter(r_in, rec, sampler)
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        let c = self.mask.value(rec.u, rec.v, rec.p);
+        self.material.opacity(rec) * (c.r + c.g + c.b) / 3.0
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.material.albedo(rec)
+    }
 }
 
 pub struct Lambertian {
     albedo: Color,
+    sheen: Option<Color>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
-        Lambertian { albedo }
+        Lambertian {
+            albedo,
+            sheen: None,
+        }
+    }
+
+    /// Adds a fabric sheen lobe of the given color that brightens grazing angles.
+    pub fn with_sheen(mut self, sheen: Color) -> Self {
+        self.sheen = Some(sheen);
+        self
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        let mut scatter_direction = rec.normal + sample_unit_sphere(sampler.get_2d());
 
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
 
-        let scattered = Ray::new(rec.p, scatter_direction);
+        let scattered = r_in.spawn(rec.p, scatter_direction);
 
-        let attenuation = self.albedo;
+        let attenuation = match self.sheen {
+            Some(sheen) => layer_sheen(self.albedo, sheen, r_in.direction, scatter_direction),
+            None => self.albedo,
+        };
         Some((attenuation, scattered))
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+/// Rough diffuse reflection (Oren–Nayar), for clay, concrete and cloth that
+/// stay brighter than Lambertian at grazing angles. `roughness` is the standard
+/// deviation of the microfacet slope angle in radians; zero is Lambertian.
+pub struct OrenNayar {
+    albedo: Color,
+    a: f64,
+    b: f64,
+    sheen: Option<Color>,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Color, roughness: f64) -> Self {
+        let sigma2 = roughness * roughness;
+        OrenNayar {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+            sheen: None,
+        }
+    }
+
+    /// Adds a fabric sheen lobe of the given color that brightens grazing angles.
+    pub fn with_sheen(mut self, sheen: Color) -> Self {
+        self.sheen = Some(sheen);
+        self
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        let uvw = Onb::new(rec.normal);
+        let wi = sample_cosine_hemisphere(sampler.get_2d());
+        let wo = Vec3::new(
+            Vec3::dot(-r_in.direction, uvw.u()),
+            Vec3::dot(-r_in.direction, uvw.v()),
+            Vec3::dot(-r_in.direction, uvw.w()),
+        );
+        let wo = Vec3::unit_vector(wo);
+
+        // Cosine-weighted sampling cancels the cosine and 1/pi of the BRDF,
+        // leaving only the Oren–Nayar factor.
+        let cos_theta_i = wi.z;
+        let cos_theta_o = wo.z.max(0.0);
+        let sin_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0).sqrt();
+        let sin_theta_o = (1.0 - cos_theta_o * cos_theta_o).max(0.0).sqrt();
+
+        let max_cos = if sin_theta_i > 1e-4 && sin_theta_o > 1e-4 {
+            let cos_dphi = (wi.x * wo.x + wi.y * wo.y) / (sin_theta_i * sin_theta_o);
+            cos_dphi.max(0.0)
+        } else {
+            0.0
+        };
+        let (sin_alpha, tan_beta) = if cos_theta_i > cos_theta_o {
+            (sin_theta_o, sin_theta_i / cos_theta_i)
+        } else {
+            (sin_theta_i, sin_theta_o / cos_theta_o.max(1e-8))
+        };
+        let factor = self.a + self.b * max_cos * sin_alpha * tan_beta;
+
+        let direction = uvw.transform(wi);
+        let mut attenuation = factor * self.albedo;
+        if let Some(sheen) = self.sheen {
+            attenuation = layer_sheen(attenuation, sheen, r_in.direction, direction);
+        }
+        Some((attenuation, r_in.spawn(rec.p, direction)))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+/// Throughput of a sheen lobe over a base lobe for a cosine-sampled bounce.
+/// The sheen grows as the incoming and outgoing directions spread apart
+/// towards grazing, and the base only receives the light the sheen does not
+/// reflect, so the sum stays at most one for albedos of at most one.
+fn layer_sheen(base: Color, sheen: Color, incoming: Vec3, scattered: Vec3) -> Color {
+    let wo = -Vec3::unit_vector(incoming);
+    let wi = Vec3::unit_vector(scattered);
+    let half = wo + wi;
+    if half.near_zero() {
+        return base;
+    }
+    let cos_theta_d = Vec3::dot(wi, Vec3::unit_vector(half)).clamp(0.0, 1.0);
+    let weight = (1.0 - cos_theta_d).powi(5);
+    let layer = |base: f64, sheen: f64| {
+        let s = weight * sheen.clamp(0.0, 1.0);
+        (1.0 - s) * base + s
+    };
+    Color::new(
+        layer(base.r, sheen.r),
+        layer(base.g, sheen.g),
+        layer(base.b, sheen.b),
+    )
+}
+
+/// A transparent coating a few hundred nanometers thick. Light reflected off
+/// the top and bottom of the film interferes, giving the angle-dependent colors
+/// of soap bubbles, oil slicks and anodized metals.
+#[derive(Debug, Clone, Copy)]
+pub struct ThinFilm {
+    /// Film thickness in nanometers.
+    pub thickness: f64,
+    pub ior: f64,
+}
+
+/// Representative wavelengths (nm) for the red, green and blue channels.
+const RGB_WAVELENGTHS: [f64; 3] = [650.0, 532.0, 450.0];
+
+impl ThinFilm {
+    pub fn new(thickness: f64, ior: f64) -> Self {
+        ThinFilm { thickness, ior }
+    }
+
+    /// Per-channel reflectance of the film sitting between a medium of index
+    /// `eta_i` (where the ray comes from) and a dielectric of index `eta_t`.
+    pub fn dielectric_reflectance(&self, cos_i: f64, eta_i: f64, eta_t: f64) -> Color {
+        let Some((cos_f, r12_s, r12_p)) = self.top_interface(cos_i, eta_i) else {
+            return Color::new(1.0, 1.0, 1.0);
+        };
+
+        let sin_t2 = (self.ior / eta_t).powi(2) * (1.0 - cos_f * cos_f);
+        let (r23_s, r23_p) = if sin_t2 >= 1.0 {
+            (1.0, 1.0)
+        } else {
+            let cos_t = (1.0 - sin_t2).sqrt();
+            (
+                (self.ior * cos_f - eta_t * cos_t) / (self.ior * cos_f + eta_t * cos_t),
+                (eta_t * cos_f - self.ior * cos_t) / (eta_t * cos_f + self.ior * cos_t),
+            )
+        };
+
+        let r = RGB_WAVELENGTHS.map(|lambda| {
+            let phase = self.phase(cos_f, lambda);
+            0.5 * (airy_reflectance(r12_s, r23_s, phase) + airy_reflectance(r12_p, r23_p, phase))
+        });
+        Color::new(r[0], r[1], r[2])
+    }
+
+    /// Per-channel reflectance of the film on top of a metal whose bare
+    /// reflectance is `albedo`.
+    pub fn conductor_reflectance(&self, cos_i: f64, albedo: Color) -> Color {
+        let Some((cos_f, r12_s, r12_p)) = self.top_interface(cos_i, 1.0) else {
+            return albedo;
+        };
+
+        // Metals flip the phase of reflected light, so the film/metal amplitude
+        // coefficient is taken as the negative square root of the reflectance.
+        let substrate = [albedo.r, albedo.g, albedo.b];
+        let r: [f64; 3] = std::array::from_fn(|c| {
+            let r23 = -substrate[c].max(0.0).sqrt();
+            let phase = self.phase(cos_f, RGB_WAVELENGTHS[c]);
+            0.5 * (airy_reflectance(r12_s, r23, phase) + airy_reflectance(r12_p, r23, phase))
+        });
+        Color::new(r[0], r[1], r[2])
+    }
+
+    /// Refracts into the film, returning the cosine inside it and the s/p
+    /// amplitude coefficients of the outer interface, or `None` on total
+    /// internal reflection.
+    fn top_interface(&self, cos_i: f64, eta_i: f64) -> Option<(f64, f64, f64)> {
+        let cos_i = cos_i.clamp(0.0, 1.0);
+        let sin_f2 = (eta_i / self.ior).powi(2) * (1.0 - cos_i * cos_i);
+        if sin_f2 >= 1.0 {
+            return None;
+        }
+        let cos_f = (1.0 - sin_f2).sqrt();
+        let r12_s = (eta_i * cos_i - self.ior * cos_f) / (eta_i * cos_i + self.ior * cos_f);
+        let r12_p = (self.ior * cos_i - eta_i * cos_f) / (self.ior * cos_i + eta_i * cos_f);
+        Some((cos_f, r12_s, r12_p))
+    }
+
+    fn phase(&self, cos_f: f64, wavelength: f64) -> f64 {
+        4.0 * PI * self.ior * self.thickness * cos_f / wavelength
+    }
+}
+
+/// Reflectance of a single film from the amplitude coefficients of its two
+/// interfaces and the round-trip phase difference.
+fn airy_reflectance(r12: f64, r23: f64, phase: f64) -> f64 {
+    let cross = 2.0 * r12 * r23 * phase.cos();
+    let denominator = 1.0 + r12 * r12 * r23 * r23 + cross;
+    // Both interfaces reflecting everything, as at grazing incidence, leaves
+    // 0 / 0; all of the light is reflected then.
+    if denominator <= f64::EPSILON {
+        return 1.0;
+    }
+    (r12 * r12 + r23 * r23 + cross) / denominator
+}
+
+/// Perturbs the shading normal of a material with a tangent-space normal map,
+/// where red, green and blue encode the tangent, bitangent and normal axes.
+pub struct NormalMap<M, T> {
+    material: M,
+    map: T,
+    strength: f64,
+}
+
+impl<M: Material, T: Texture> NormalMap<M, T> {
+    pub fn new(material: M, map: T, strength: f64) -> Self {
+        NormalMap {
+            material,
+            map,
+            strength,
+        }
+    }
+}
+
+impl<M: Material, T: Texture> Material for NormalMap<M, T> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        let c = self.map.value(rec.u, rec.v, rec.p);
+        let normal = (self.strength * (2.0 * c.r - 1.0)) * rec.tangent
+            + (self.strength * (2.0 * c.g - 1.0)) * rec.bitangent
+            + (2.0 * c.b - 1.0) * rec.normal;
+        self.material
+            .scatter(r_in, &rec.with_shading_normal(normal), sampler)
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.material.opacity(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.material.albedo(rec)
+    }
+}
+
+/// Perturbs the shading normal of a material from the slope of a scalar height
+/// texture (the average of its channels), scaled by `scale`.
+pub struct BumpMap<M, T> {
+    material: M,
+    height: T,
+    scale: f64,
+}
+
+impl<M: Material, T: Texture> BumpMap<M, T> {
+    pub fn new(material: M, height: T, scale: f64) -> Self {
+        BumpMap {
+            material,
+            height,
+            scale,
+        }
+    }
+
+    fn height_at(&self, u: f64, v: f64, rec: &HitRecord) -> f64 {
+        let c = self.height.value(u, v, rec.p);
+        (c.r + c.g + c.b) / 3.0
+    }
+}
+
+impl<M: Material, T: Texture> Material for BumpMap<M, T> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        const DELTA: f64 = 1e-3;
+        let h = self.height_at(rec.u, rec.v, rec);
+        let dhdu = (self.height_at(rec.u + DELTA, rec.v, rec) - h) / DELTA;
+        let dhdv = (self.height_at(rec.u, rec.v + DELTA, rec) - h) / DELTA;
+
+        let normal = rec.normal - self.scale * (dhdu * rec.tangent + dhdv * rec.bitangent);
+        self.material
+            .scatter(r_in, &rec.with_shading_normal(normal), sampler)
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.material.opacity(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.material.albedo(rec)
+    }
 }
 
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Metal {
@@ -39,29 +407,60 @@ impl Metal {
         Metal {
             albedo,
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
+            thin_film: None,
         }
     }
+
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Metal {
+        self.thin_film = Some(film);
+        self
+    }
 }
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
         let mut reflected = Vec3::reflect(r_in.direction, rec.normal);
-        reflected = Vec3::unit_vector(reflected) + (self.fuzz * Vec3::random_unit_vector());
-        let scattered = Ray::new(rec.p, reflected);
-        let attenuation = self.albedo;
+        reflected =
+            Vec3::unit_vector(reflected) + (self.fuzz * sample_unit_sphere(sampler.get_2d()));
+        let scattered = r_in.spawn(rec.p, reflected);
+        let attenuation = match self.thin_film {
+            Some(film) => {
+                let cos_i = Vec3::dot(-Vec3::unit_vector(r_in.direction), rec.normal);
+                film.conductor_reflectance(cos_i, self.albedo)
+            }
+            None => self.albedo,
+        };
         if Vec3::dot(scattered.direction, rec.normal) > 0.0 {
             return Some((attenuation, scattered));
         }
         None
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
 }
 
 pub struct Dialectric {
     refraction_index: f64,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Dialectric {
     pub fn new(refraction_index: f64) -> Self {
-        Dialectric { refraction_index }
+        Dialectric {
+            refraction_index,
+            thin_film: None,
+        }
+    }
+
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Self {
+        self.thin_film = Some(film);
+        self
     }
 
     pub fn reflactance(cosine: f64, refraction_index: f64) -> f64 {
@@ -72,7 +471,12 @@ impl Dialectric {
 }
 
 impl Material for Dialectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
         let attenuation = Color::new(1.0, 1.0, 1.0);
         let ri = if rec.front_face {
             1.0 / self.refraction_index
@@ -86,13 +490,276 @@ impl Material for Dialectric {
 
         let cannot_refract = ri * sin_theta > 1.0;
 
-        let direction = if cannot_refract || Dialectric::reflactance(cos_theta, ri) > random_f64() {
-            Vec3::reflect(unit_direction, rec.normal)
-        } else {
-            Vec3::refract(unit_direction, rec.normal, ri)
-        };
+        if let Some(film) = self.thin_film {
+            if cannot_refract {
+                let scattered = r_in.spawn(rec.p, Vec3::reflect(unit_direction, rec.normal));
+                return Some((attenuation, scattered));
+            }
 
-        let scattered = Ray::new(rec.p, direction);
+            let (eta_i, eta_t) = if rec.front_face {
+                (1.0, self.refraction_index)
+            } else {
+                (self.refraction_index, 1.0)
+            };
+            // Choose reflection with the average reflectance and reweight each
+            // channel so the color of the interference survives.
+            let reflectance = film.dielectric_reflectance(cos_theta, eta_i, eta_t);
+            let p = (reflectance.r + reflectance.g + reflectance.b) / 3.0;
+            return if sampler.get_1d() < p {
+                let scattered = r_in.spawn(rec.p, Vec3::reflect(unit_direction, rec.normal));
+                Some(((1.0 / p) * reflectance, scattered))
+            } else {
+                let transmittance = Color::new(
+                    1.0 - reflectance.r,
+                    1.0 - reflectance.g,
+                    1.0 - reflectance.b,
+                );
+                let scattered = r_in.spawn(rec.p, Vec3::refract(unit_direction, rec.normal, ri));
+                Some(((1.0 / (1.0 - p)) * transmittance, scattered))
+            };
+        }
+
+        let direction =
+            if cannot_refract || Dialectric::reflactance(cos_theta, ri) > sampler.get_1d() {
+                Vec3::reflect(unit_direction, rec.normal)
+            } else {
+                Vec3::refract(unit_direction, rec.normal, ri)
+            };
+
+        let scattered = r_in.spawn(rec.p, direction);
         Some((attenuation, scattered))
     }
 }
+
+/// A translucent material such as skin, wax, marble or milk. Light refracts
+/// into the object and performs a random walk through its interior, scattering
+/// isotropically after exponentially distributed distances with the given mean
+/// free path and losing `1 - albedo` of its energy at every event.
+///
+/// The walk is carried by the camera's recursion: a ray travelling inside the
+/// object hits the back face of the same surface, and the material decides
+/// whether it scattered in the medium before reaching it. Objects must
+/// therefore be closed, and dense media need a correspondingly large
+/// `max_depth`.
+pub struct Subsurface {
+    albedo: Color,
+    mean_free_path: f64,
+    boundary: Dialectric,
+}
+
+impl Subsurface {
+    pub fn new(albedo: Color, mean_free_path: f64, refraction_index: f64) -> Self {
+        Subsurface {
+            albedo,
+            mean_free_path,
+            boundary: Dialectric::new(refraction_index),
+        }
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        if rec.front_face {
+            return self.boundary.scatter(r_in, rec, sampler);
+        }
+
+        let ray_length = Vec3::length(r_in.direction);
+        let distance_to_boundary = rec.t * ray_length;
+        let scatter_distance = -self.mean_free_path * (1.0 - sampler.get_1d()).ln();
+        if scatter_distance >= distance_to_boundary {
+            return self.boundary.scatter(r_in, rec, sampler);
+        }
+
+        let scattered = r_in.spawn(
+            r_in.at(scatter_distance / ray_length),
+            sample_unit_sphere(sampler.get_2d()),
+        );
+        Some((self.albedo, scattered))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sampler::IndependentSampler, vec3::Point3};
+
+    /// Scatters `n` independent samples of `ray` off `rec`.
+    fn scatter_samples(
+        material: &dyn Material,
+        ray: &Ray,
+        rec: &HitRecord,
+        n: usize,
+    ) -> Vec<Option<(Color, Ray)>> {
+        let mut sampler = IndependentSampler::new(1);
+        (0..n)
+            .map(|i| {
+                sampler.start_pixel_sample((0, 0), i);
+                material.scatter(ray, rec, &mut sampler)
+            })
+            .collect()
+    }
+
+    fn assert_in_unit_range(c: Color) {
+        for value in [c.r, c.g, c.b] {
+            assert!((0.0..=1.0).contains(&value), "{c:?} outside [0, 1]");
+        }
+    }
+
+    #[test]
+    fn thin_film_reflectance_stays_in_unit_range() {
+        for thickness in [0.0, 120.0, 300.0, 550.0, 1000.0] {
+            for ior in [1.2, 1.33, 1.5, 2.4] {
+                let film = ThinFilm::new(thickness, ior);
+                for step in 0..=20 {
+                    let cos_i = step as f64 / 20.0;
+                    assert_in_unit_range(film.dielectric_reflectance(cos_i, 1.0, 1.5));
+                    assert_in_unit_range(film.dielectric_reflectance(cos_i, 1.5, 1.0));
+                    for albedo in [0.0, 0.5, 0.95, 1.0] {
+                        let albedo = Color::new(albedo, albedo * 0.8, albedo * 0.3);
+                        assert_in_unit_range(film.conductor_reflectance(cos_i, albedo));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vanishing_film_reflects_like_the_bare_surface() {
+        // At normal incidence air/glass reflects ((1 - 1.5) / (1 + 1.5))^2.
+        let r = ThinFilm::new(0.0, 1.33).dielectric_reflectance(1.0, 1.0, 1.5);
+        for value in [r.r, r.g, r.b] {
+            assert!((value - 0.04).abs() < 1e-12, "{value}");
+        }
+    }
+
+    #[test]
+    fn subsurface_walks_lose_energy_at_the_albedo() {
+        let albedo = Color::new(0.9, 0.5, 0.2);
+        let material = Subsurface::new(albedo, 1.0, 1.3);
+        // A ray inside the object, reaching the back of its surface after a
+        // distance of two mean free paths.
+        let ray = Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, 2.0));
+        let mut rec = HitRecord::new(
+            Point3::new(0.0, 0.0, 2.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            1.0,
+            &material,
+        );
+        rec.front_face = false;
+
+        let samples = scatter_samples(&material, &ray, &rec, 4000);
+        let mut scattered_inside = 0;
+        for (attenuation, scattered) in samples.into_iter().flatten() {
+            assert_in_unit_range(attenuation);
+            if scattered.origin.z < 2.0 - 1e-9 {
+                scattered_inside += 1;
+                assert!(scattered.origin.z >= 0.0);
+                assert_eq!(
+                    (attenuation.r, attenuation.g, attenuation.b),
+                    (albedo.r, albedo.g, albedo.b)
+                );
+            }
+        }
+        // Free paths are exponential, so 1 - e^-2 of the walks scatter first.
+        let fraction = scattered_inside as f64 / 4000.0;
+        assert!(
+            (fraction - (1.0 - (-2.0f64).exp())).abs() < 0.03,
+            "{fraction}"
+        );
+    }
+
+    #[test]
+    fn subsurface_boundary_neither_gains_nor_loses_energy() {
+        let material = Subsurface::new(Color::new(0.8, 0.8, 0.8), 0.5, 1.5);
+        let ray = Ray::new(Point3::new(0.0, 0.0, 2.0), Vec3::new(0.3, 0.0, -1.0));
+        let rec = HitRecord::new(Point3::zero(), Vec3::new(0.0, 0.0, 1.0), 1.0, &material);
+        for (attenuation, _) in scatter_samples(&material, &ray, &rec, 256)
+            .into_iter()
+            .flatten()
+        {
+            assert_eq!(
+                (attenuation.r, attenuation.g, attenuation.b),
+                (1.0, 1.0, 1.0)
+            );
+        }
+    }
+
+    /// Mean throughput of `n` bounces of a ray arriving at `angle` degrees
+    /// from the normal of a surface facing up.
+    fn directional_albedo(material: &dyn Material, angle: f64, n: usize) -> Color {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let ray = Ray::new(Point3::new(-sin, 0.0, cos), Vec3::new(sin, 0.0, -cos));
+        let rec = HitRecord::new(Point3::zero(), Vec3::new(0.0, 0.0, 1.0), 1.0, material);
+        let sum = scatter_samples(material, &ray, &rec, n)
+            .into_iter()
+            .flatten()
+            .fold(Color::new(0.0, 0.0, 0.0), |sum, (attenuation, _)| {
+                sum + attenuation
+            });
+        (1.0 / n as f64) * sum
+    }
+
+    #[test]
+    fn smooth_oren_nayar_is_lambertian() {
+        let albedo = Color::new(0.7, 0.4, 0.1);
+        let material = OrenNayar::new(albedo, 0.0);
+        let ray = Ray::new(Point3::new(-1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, -1.0));
+        let rec = HitRecord::new(Point3::zero(), Vec3::new(0.0, 0.0, 1.0), 1.0, &material);
+        for sample in scatter_samples(&material, &ray, &rec, 256) {
+            let (attenuation, scattered) = sample.unwrap();
+            assert_eq!(
+                (attenuation.r, attenuation.g, attenuation.b),
+                (albedo.r, albedo.g, albedo.b)
+            );
+            assert!(scattered.direction.z >= 0.0);
+        }
+    }
+
+    #[test]
+    fn diffuse_lobes_do_not_create_energy() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let sheen = Color::new(1.0, 0.8, 0.6);
+        let materials: [&dyn Material; 5] = [
+            &OrenNayar::new(white, 0.3),
+            &OrenNayar::new(white, 1.0),
+            &OrenNayar::new(white, 1.0).with_sheen(sheen),
+            &Lambertian::new(white).with_sheen(sheen),
+            &Lambertian::new(Color::new(0.5, 0.5, 0.5)).with_sheen(white),
+        ];
+        for material in materials {
+            for angle in [0.0, 30.0, 60.0, 85.0] {
+                let albedo = directional_albedo(material, angle, 20000);
+                for value in [albedo.r, albedo.g, albedo.b] {
+                    assert!(value <= 1.0 + 1e-3, "albedo {value} at {angle} degrees");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sheen_layer_stays_in_unit_range() {
+        let mut sampler = IndependentSampler::new(2);
+        for i in 0..1000 {
+            sampler.start_pixel_sample((0, 0), i);
+            let incoming = sample_unit_sphere(sampler.get_2d());
+            let scattered = sample_unit_sphere(sampler.get_2d());
+            let (a, b) = sampler.get_2d();
+            let layered = layer_sheen(
+                Color::new(a, b, 1.0),
+                Color::new(b, 1.0, a),
+                incoming,
+                scattered,
+            );
+            assert_in_unit_range(layered);
+        }
+    }
+}
@@ -6,11 +6,42 @@ use crate::vec3::{Point3, Vec3};
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    /// Scene time the ray travels at, which places animated objects.
+    pub time: f64,
+    /// Seed of the render the ray belongs to, mixed into stochastic alpha
+    /// tests.
+    pub seed: u64,
 }
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            time: 0.0,
+            seed: 0,
+        }
+    }
+
+    /// Ray leaving `origin` at the same time and in the same render as this
+    /// one, as when it scatters.
+    pub fn spawn(&self, origin: Point3, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time: self.time,
+            seed: self.seed,
+        }
+    }
+
+    pub fn with_time(mut self, time: f64) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
     }
 
     pub fn at(&self, t: f64) -> Point3 {
@@ -0,0 +1,664 @@
+use std::{
+    f64::consts::{FRAC_PI_2, FRAC_PI_4, PI},
+    sync::OnceLock,
+};
+
+use crate::{
+    util::{hash_to_f64, hash_u64s, mix_bits},
+    vec3::Vec3,
+};
+
+/// Source of the random numbers used to build one camera sample: the film
+/// position, the lens position and every bounce after that. Each call to
+/// `get_1d`/`get_2d` consumes the next dimension of the current sample.
+pub trait Sampler {
+    /// Starts sample number `sample_index` of the pixel at `pixel` (column, row).
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplerType {
+    /// Independent uniform random numbers.
+    Independent,
+    /// Jittered samples with one stratum per sample in every dimension.
+    Stratified,
+    /// Owen-scrambled Halton sequence.
+    Halton,
+    /// Owen-scrambled Sobol sequence.
+    Sobol,
+    /// Screen-space blue noise, advanced over samples by a low discrepancy
+    /// rotation.
+    BlueNoise,
+}
+
+impl SamplerType {
+    /// Creates a sampler whose values depend only on `seed`, the pixel and the
+    /// sample index, never on the order in which samples are taken.
+    pub fn build(self, samples_per_pixel: usize, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerType::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerType::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerType::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerType::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerType::BlueNoise => Box::new(BlueNoiseSampler::new(seed)),
+        }
+    }
+}
+
+/// Largest f64 below one, so that samples stay in [0, 1).
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+/// Uniform random numbers from a generator reseeded for every pixel sample.
+pub struct IndependentSampler {
+    seed: u64,
+    rng: fastrand::Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        IndependentSampler {
+            seed,
+            rng: fastrand::Rng::with_seed(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.rng.seed(hash_u64s(&[
+            self.seed,
+            pixel.0 as u64,
+            pixel.1 as u64,
+            sample_index as u64,
+        ]));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.f64()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.f64(), self.rng.f64())
+    }
+}
+
+/// Splits every dimension into `samples_per_pixel` strata (a square grid for
+/// 2D requests) and visits them in a per-pixel, per-dimension random order, so
+/// that each pass over the pixel's samples covers every stratum once.
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: usize,
+    grid_size: usize,
+    pixel: (usize, usize),
+    sample_index: usize,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        StratifiedSampler {
+            seed,
+            samples_per_pixel,
+            grid_size: (samples_per_pixel as f64).sqrt().ceil() as usize,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn hash(&self, salt: u64) -> u64 {
+        hash_u64s(&[
+            self.seed,
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.dimension,
+            (self.sample_index / self.samples_per_pixel) as u64,
+            salt,
+        ])
+    }
+
+    fn stratum(&self, count: usize) -> usize {
+        let index = self.sample_index % self.samples_per_pixel;
+        permutation_element(index as u32, count as u32, self.hash(0) as u32) as usize
+    }
+
+    fn jitter(&self, salt: u64) -> f64 {
+        hash_to_f64(hash_u64s(&[self.hash(salt), self.sample_index as u64]))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let stratum = self.stratum(self.samples_per_pixel);
+        let u = (stratum as f64 + self.jitter(1)) / self.samples_per_pixel as f64;
+        self.dimension += 1;
+        u.min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let n = self.grid_size;
+        let stratum = self.stratum(n * n);
+        let x = (stratum % n) as f64 + self.jitter(1);
+        let y = (stratum / n) as f64 + self.jitter(2);
+        self.dimension += 2;
+        (
+            (x / n as f64).min(ONE_MINUS_EPSILON),
+            (y / n as f64).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Halton sequence with one prime base per dimension, decorrelated between
+/// pixels by per-pixel Owen scrambling. Dimensions past the prime table fall
+/// back to hashed random numbers.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: (usize, usize),
+    sample_index: usize,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        HaltonSampler {
+            seed,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn sample_dimension(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        let hash = hash_u64s(&[
+            self.seed,
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            dimension as u64,
+        ]);
+        match PRIMES.get(dimension) {
+            Some(&base) => owen_scrambled_radical_inverse(base, self.sample_index as u64, hash),
+            None => hash_to_f64(hash_u64s(&[hash, self.sample_index as u64])),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.sample_dimension()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.sample_dimension(), self.sample_dimension())
+    }
+}
+
+/// Radical inverse of `a` in `base` with every digit permuted by a hash of the
+/// digits above it, which is Owen scrambling.
+fn owen_scrambled_radical_inverse(base: u64, mut a: u64, hash: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut reversed_digits: u64 = 0;
+    let mut inv_base_m = 1.0;
+    while 1.0 - inv_base_m < 1.0 {
+        let next = a / base;
+        let digit = a - next * base;
+        let digit_hash = mix_bits(hash ^ reversed_digits);
+        let digit = permutation_element(digit as u32, base as u32, digit_hash as u32) as u64;
+        reversed_digits = reversed_digits * base + digit;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    (inv_base_m * reversed_digits as f64).min(ONE_MINUS_EPSILON)
+}
+
+/// Padded 2D Sobol points with hash-based Owen scrambling (Burley 2020). Every
+/// request gets its own shuffle of the sample index and its own scramble, so
+/// dimensions are decorrelated from each other and from neighbouring pixels.
+pub struct SobolSampler {
+    seed: u64,
+    pixel: (usize, usize),
+    sample_index: usize,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        SobolSampler {
+            seed,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn seed(&mut self) -> u32 {
+        let seed = hash_u64s(&[
+            self.seed,
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.dimension,
+        ]);
+        self.dimension += 1;
+        seed as u32
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let seed = self.seed();
+        let index = nested_uniform_scramble(self.sample_index as u32, seed);
+        let x = nested_uniform_scramble(sobol(index, 0), mix_bits(seed as u64 ^ 1) as u32);
+        u32_to_unit_f64(x)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let seed = self.seed();
+        let index = nested_uniform_scramble(self.sample_index as u32, seed);
+        let x = nested_uniform_scramble(sobol(index, 0), mix_bits(seed as u64 ^ 1) as u32);
+        let y = nested_uniform_scramble(sobol(index, 1), mix_bits(seed as u64 ^ 2) as u32);
+        (u32_to_unit_f64(x), u32_to_unit_f64(y))
+    }
+}
+
+/// 32-bit Sobol generator matrices for the first two dimensions.
+fn sobol_directions() -> &'static [[u32; 32]; 2] {
+    static DIRECTIONS: OnceLock<[[u32; 32]; 2]> = OnceLock::new();
+    DIRECTIONS.get_or_init(|| {
+        let mut directions = [[0u32; 32]; 2];
+        for (bit, v) in directions[0].iter_mut().enumerate() {
+            *v = 1 << (31 - bit);
+        }
+        // Second dimension: primitive polynomial x + 1 with m_1 = 1.
+        directions[1][0] = 1 << 31;
+        for bit in 1..32 {
+            let prev = directions[1][bit - 1];
+            directions[1][bit] = prev ^ (prev >> 1);
+        }
+        directions
+    })
+}
+
+fn sobol(index: u32, dimension: usize) -> u32 {
+    let directions = &sobol_directions()[dimension];
+    let mut x = 0;
+    for (bit, &v) in directions.iter().enumerate() {
+        if (index >> bit) & 1 == 1 {
+            x ^= v;
+        }
+    }
+    x
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn u32_to_unit_f64(x: u32) -> f64 {
+    (x as f64 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+/// Side of the square blue noise tile.
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Offsets a tiled blue noise mask differently for every dimension, and
+/// rotates it over samples with the golden ratio (1D) or R2 (2D) sequence, so
+/// errors are pushed to high screen-space frequencies at every sample count.
+pub struct BlueNoiseSampler {
+    seed: u64,
+    mask: &'static [f64],
+    pixel: (usize, usize),
+    sample_index: usize,
+    dimension: u64,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(seed: u64) -> Self {
+        BlueNoiseSampler {
+            seed,
+            mask: blue_noise_mask(),
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn mask_value(&mut self) -> f64 {
+        let offset = hash_u64s(&[self.seed, self.dimension]);
+        self.dimension += 1;
+        let x = (self.pixel.0 + (offset & 0xffff) as usize) % BLUE_NOISE_SIZE;
+        let y = (self.pixel.1 + (offset >> 16 & 0xffff) as usize) % BLUE_NOISE_SIZE;
+        self.mask[y * BLUE_NOISE_SIZE + x]
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        const GOLDEN: f64 = 0.618_033_988_749_894_9;
+        let n = self.sample_index as f64;
+        (self.mask_value() + n * GOLDEN)
+            .fract()
+            .min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        // Reciprocals of the plastic number and its square.
+        const R2: (f64, f64) = (0.754_877_666_246_692_7, 0.569_840_290_998_053_3);
+        let n = self.sample_index as f64;
+        let u = (self.mask_value() + n * R2.0).fract();
+        let v = (self.mask_value() + n * R2.1).fract();
+        (u.min(ONE_MINUS_EPSILON), v.min(ONE_MINUS_EPSILON))
+    }
+}
+
+/// Blue noise threshold mask generated once per process with the
+/// void-and-cluster algorithm. Values are the pixel ranks scaled to [0, 1).
+fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(|| {
+        const N: usize = BLUE_NOISE_SIZE;
+        const SIGMA: f64 = 1.5;
+
+        // Toroidal Gaussian energy kernel indexed by offset.
+        let mut kernel = vec![0.0; N * N];
+        for dy in 0..N {
+            for dx in 0..N {
+                let x = dx.min(N - dx) as f64;
+                let y = dy.min(N - dy) as f64;
+                kernel[dy * N + dx] = (-(x * x + y * y) / (2.0 * SIGMA * SIGMA)).exp();
+            }
+        }
+        let update = |energy: &mut [f64], p: usize, sign: f64| {
+            let (px, py) = (p % N, p / N);
+            for y in 0..N {
+                for x in 0..N {
+                    let dx = (x + N - px) % N;
+                    let dy = (y + N - py) % N;
+                    energy[y * N + x] += sign * kernel[dy * N + dx];
+                }
+            }
+        };
+        let extreme = |energy: &[f64], pattern: &[bool], wanted: bool, tightest: bool| {
+            (0..N * N)
+                .filter(|&p| pattern[p] == wanted)
+                .max_by(|&a, &b| {
+                    let ord = energy[a].total_cmp(&energy[b]);
+                    if tightest { ord } else { ord.reverse() }
+                })
+                .unwrap()
+        };
+
+        // Initial pattern: a sparse deterministic random set relaxed until the
+        // tightest cluster is also the largest void.
+        let mut pattern = vec![false; N * N];
+        let mut energy = vec![0.0; N * N];
+        let initial = N * N / 10;
+        let mut placed = 0;
+        let mut i = 0;
+        while placed < initial {
+            let p = (mix_bits(i) % (N * N) as u64) as usize;
+            i += 1;
+            if !pattern[p] {
+                pattern[p] = true;
+                update(&mut energy, p, 1.0);
+                placed += 1;
+            }
+        }
+        loop {
+            let cluster = extreme(&energy, &pattern, true, true);
+            pattern[cluster] = false;
+            update(&mut energy, cluster, -1.0);
+            let void = extreme(&energy, &pattern, false, false);
+            pattern[void] = true;
+            update(&mut energy, void, 1.0);
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut rank = vec![0usize; N * N];
+
+        // Phase 1: rank the initial points by repeatedly removing the tightest
+        // cluster.
+        let mut shrinking = pattern.clone();
+        let mut shrinking_energy = energy.clone();
+        for r in (0..initial).rev() {
+            let cluster = extreme(&shrinking_energy, &shrinking, true, true);
+            shrinking[cluster] = false;
+            update(&mut shrinking_energy, cluster, -1.0);
+            rank[cluster] = r;
+        }
+
+        // Phase 2: fill the remaining pixels, largest void first.
+        for r in initial..N * N {
+            let void = extreme(&energy, &pattern, false, false);
+            pattern[void] = true;
+            update(&mut energy, void, 1.0);
+            rank[void] = r;
+        }
+
+        rank.into_iter()
+            .map(|r| (r as f64 + 0.5) / (N * N) as f64)
+            .collect()
+    })
+}
+
+/// Element `i` of a random permutation of [0, l) selected by `p`, computed
+/// without storing the permutation (Kensler 2013).
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % l
+}
+
+/// Uniformly distributed direction on the unit sphere.
+pub fn sample_unit_sphere(u: (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Direction around +z, distributed proportionally to its cosine.
+pub fn sample_cosine_hemisphere(u: (f64, f64)) -> Vec3 {
+    let d = sample_unit_disk(u);
+    let z = (1.0 - d.x * d.x - d.y * d.y).max(0.0).sqrt();
+    Vec3::new(d.x, d.y, z)
+}
+
+/// Point in the unit disk (z = 0) using the concentric mapping, which keeps
+/// the stratification of the input samples.
+pub fn sample_unit_disk(u: (f64, f64)) -> Vec3 {
+    let (ox, oy) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+    if ox == 0.0 && oy == 0.0 {
+        return Vec3::zero();
+    }
+    let (r, theta) = if ox.abs() > oy.abs() {
+        (ox, FRAC_PI_4 * (oy / ox))
+    } else {
+        (oy, FRAC_PI_2 - FRAC_PI_4 * (ox / oy))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [SamplerType; 5] = [
+        SamplerType::Independent,
+        SamplerType::Stratified,
+        SamplerType::Halton,
+        SamplerType::Sobol,
+        SamplerType::BlueNoise,
+    ];
+
+    /// The first `samples` 2D samples of `pixel`, for dimension `dimension`
+    /// counted in 2D requests.
+    fn samples_2d(
+        sampler: &mut dyn Sampler,
+        pixel: (usize, usize),
+        samples: usize,
+        dimension: usize,
+    ) -> Vec<(f64, f64)> {
+        (0..samples)
+            .map(|i| {
+                sampler.start_pixel_sample(pixel, i);
+                for _ in 0..dimension {
+                    sampler.get_2d();
+                }
+                sampler.get_2d()
+            })
+            .collect()
+    }
+
+    /// Whether every cell of an `nx` by `ny` grid holds exactly one point.
+    fn one_per_cell(points: &[(f64, f64)], nx: usize, ny: usize) -> bool {
+        let mut counts = vec![0; nx * ny];
+        for &(x, y) in points {
+            counts[(y * ny as f64) as usize * nx + (x * nx as f64) as usize] += 1;
+        }
+        counts.iter().all(|&c| c == 1)
+    }
+
+    #[test]
+    fn samples_lie_in_unit_interval() {
+        let in_range = |u: f64| (0.0..1.0).contains(&u);
+        for sampler_type in ALL {
+            let mut sampler = sampler_type.build(16, 7);
+            for pixel in [(0, 0), (3, 5), (63, 64), (1000, 17)] {
+                for i in 0..64 {
+                    sampler.start_pixel_sample(pixel, i);
+                    // Enough dimensions to run past the Halton prime table.
+                    for _ in 0..24 {
+                        assert!(in_range(sampler.get_1d()), "{sampler_type:?}");
+                        let (u, v) = sampler.get_2d();
+                        assert!(in_range(u) && in_range(v), "{sampler_type:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samples_depend_only_on_pixel_and_index() {
+        for sampler_type in ALL {
+            let mut a = sampler_type.build(16, 3);
+            let mut b = sampler_type.build(16, 3);
+            b.start_pixel_sample((9, 9), 5);
+            b.get_2d();
+            a.start_pixel_sample((2, 1), 4);
+            b.start_pixel_sample((2, 1), 4);
+            for _ in 0..8 {
+                assert_eq!(a.get_2d(), b.get_2d(), "{sampler_type:?}");
+                assert_eq!(a.get_1d(), b.get_1d(), "{sampler_type:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_covers_every_stratum() {
+        let mut sampler = SamplerType::Stratified.build(16, 11);
+        for pixel in [(0, 0), (5, 2)] {
+            for dimension in 0..4 {
+                let points = samples_2d(&mut *sampler, pixel, 16, dimension);
+                assert!(one_per_cell(&points, 4, 4));
+            }
+
+            let mut strata = [0; 16];
+            for i in 0..16 {
+                sampler.start_pixel_sample(pixel, i);
+                strata[(sampler.get_1d() * 16.0) as usize] += 1;
+            }
+            assert!(strata.iter().all(|&c| c == 1));
+        }
+    }
+
+    #[test]
+    fn sobol_points_are_stratified() {
+        let mut sampler = SamplerType::Sobol.build(16, 11);
+        for pixel in [(0, 0), (5, 2)] {
+            for dimension in 0..4 {
+                let points = samples_2d(&mut *sampler, pixel, 16, dimension);
+                for (nx, ny) in [(16, 1), (8, 2), (4, 4), (2, 8), (1, 16)] {
+                    assert!(one_per_cell(&points, nx, ny));
+                }
+            }
+
+            let mut strata = [0; 16];
+            for i in 0..16 {
+                sampler.start_pixel_sample(pixel, i);
+                strata[(sampler.get_1d() * 16.0) as usize] += 1;
+            }
+            assert!(strata.iter().all(|&c| c == 1));
+        }
+    }
+}
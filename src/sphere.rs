@@ -1,3 +1,5 @@
+use std::f64::consts::PI;
+
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
 use crate::material::Material;
@@ -8,6 +10,8 @@ pub struct Sphere<'a> {
     pub center: Point3,
     pub radius: f64,
     pub material: Box<dyn Material + 'a>,
+    /// Label written to the material ID AOV.
+    pub material_id: u32,
 }
 
 impl<'a> Sphere<'a> {
@@ -16,12 +20,39 @@ impl<'a> Sphere<'a> {
             center,
             radius,
             material: Box::new(mat),
+            material_id: 0,
         }
     }
+
+    pub fn with_material_id(mut self, material_id: u32) -> Self {
+        self.material_id = material_id;
+        self
+    }
+
+    // p: a given point on the sphere of radius one, centered at the origin.
+    // u: returned value [0,1] of angle around the Y axis from X=-1.
+    // v: returned value [0,1] of angle from Y=-1 to Y=+1.
+    fn get_sphere_uv(p: Point3) -> (f64, f64) {
+        let theta = (-p.y).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // Unit vectors along dp/du and dp/dv for the outward normal `n`. At the
+    // poles dp/du vanishes, so an arbitrary tangent is picked there.
+    fn get_sphere_tangents(n: Vec3) -> (Vec3, Vec3) {
+        let dpdu = Vec3::new(n.z, 0.0, -n.x);
+        let tangent = if dpdu.near_zero() {
+            Vec3::new(1.0, 0.0, 0.0)
+        } else {
+            Vec3::unit_vector(dpdu)
+        };
+        (tangent, Vec3::cross(n, tangent))
+    }
 }
 
 impl<'a> Hittable for Sphere<'a> {
-    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
         let oc = self.center - ray.origin;
 
         let a = Vec3::length_squared(ray.direction);
@@ -34,20 +65,52 @@ impl<'a> Hittable for Sphere<'a> {
         }
 
         let sqrtd = discriminant.sqrt();
-        let mut root = (h - sqrtd) / a;
-        if root <= ray_t.min || ray_t.max <= root {
-            root = (h + sqrtd) / a;
+
+        // Try the nearest root first, falling through to the far side of the
+        // sphere when the near one is cut out by the material's alpha mask.
+        for root in [(h - sqrtd) / a, (h + sqrtd) / a] {
             if !ray_t.surronds(root) {
-                return None;
+                continue;
+            }
+
+            let t = root;
+            let p = ray.at(t);
+            let normal = (p - self.center) / self.radius;
+
+            let mut rec = HitRecord::new(p, normal, t, &*self.material);
+            let (u, v) = Self::get_sphere_uv(normal);
+            let (tangent, bitangent) = Self::get_sphere_tangents(normal);
+            rec.set_uv(u, v, tangent, bitangent);
+            rec.material_id = self.material_id;
+            rec.set_normal(ray);
+            if rec.is_opaque(ray) {
+                return Some(rec);
             }
         }
+        None
+    }
+}
 
-        let t = root;
-        let p = ray.at(t);
-        let normal = (p - self.center) / self.radius;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, material::Lambertian};
 
-        let mut rec = HitRecord::new(p, normal, t, &*self.material);
-        rec.set_normal(ray);
-        Some(rec)
+    #[test]
+    fn tangent_frame_stays_right_handed_inside() {
+        let sphere = Sphere::new(
+            Point3::zero(),
+            1.0,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        );
+        let outside = Ray::new(Point3::new(0.3, 0.2, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let inside = Ray::new(Point3::new(0.3, 0.2, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        for ray in [outside, inside] {
+            let rec = sphere
+                .hit(&ray, Interval::new(0.001, f64::INFINITY))
+                .unwrap();
+            let handedness = Vec3::dot(Vec3::cross(rec.tangent, rec.bitangent), rec.normal);
+            assert!(handedness > 0.0);
+        }
     }
 }
@@ -0,0 +1,206 @@
+use std::{fs, io, path::Path};
+
+use crate::{color::Color, vec3::Point3};
+
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color;
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        SolidColor { albedo }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: Point3) -> Color {
+        self.albedo
+    }
+}
+
+/// A texture backed by a PPM image. Pixel values are scaled to [0, 1] but not
+/// gamma decoded, which is what data maps such as normal or height maps need.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        ImageTexture {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Loads an ASCII (P3) or binary (P6) PPM file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::decode(&fs::read(path)?)
+    }
+
+    fn decode(data: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        // Header tokens are whitespace separated and may be followed by comments.
+        let mut pos = 0;
+        let mut next_token = || -> Option<String> {
+            loop {
+                while pos < data.len() && data[pos].is_ascii_whitespace() {
+                    pos += 1;
+                }
+                if pos < data.len() && data[pos] == b'#' {
+                    while pos < data.len() && data[pos] != b'\n' {
+                        pos += 1;
+                    }
+                    continue;
+                }
+                break;
+            }
+            let start = pos;
+            while pos < data.len() && !data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            (start < pos).then(|| String::from_utf8_lossy(&data[start..pos]).into_owned())
+        };
+
+        let magic = next_token().ok_or_else(|| invalid("missing PPM magic number"))?;
+        let mut header = [0usize; 3];
+        for value in header.iter_mut() {
+            *value = next_token()
+                .and_then(|t| t.parse().ok())
+                .ok_or_else(|| invalid("malformed PPM header"))?;
+        }
+        let [width, height, max_value] = header;
+        if max_value == 0 || max_value > 255 {
+            return Err(invalid("unsupported PPM max value"));
+        }
+        let scale = 1.0 / max_value as f64;
+
+        let samples: Vec<f64> = match magic.as_str() {
+            "P3" => (0..width * height * 3)
+                .map(|_| {
+                    next_token()
+                        .and_then(|t| t.parse::<f64>().ok())
+                        .map(|c| c * scale)
+                        .ok_or_else(|| invalid("truncated PPM data"))
+                })
+                .collect::<io::Result<_>>()?,
+            "P6" => {
+                // Exactly one whitespace byte separates the header from the raster.
+                let start = pos + 1;
+                let raster = data
+                    .get(start..start + width * height * 3)
+                    .ok_or_else(|| invalid("truncated PPM data"))?;
+                raster.iter().map(|&c| c as f64 * scale).collect()
+            }
+            _ => return Err(invalid("unsupported PPM format")),
+        };
+
+        let pixels = samples
+            .chunks_exact(3)
+            .map(|c| Color::new(c[0], c[1], c[2]))
+            .collect();
+        Ok(ImageTexture::new(width, height, pixels))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Pixel (x, y), counted from the upper-left corner.
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    fn texel(&self, x: isize, y: isize) -> Color {
+        let x = x.rem_euclid(self.width as isize) as usize;
+        let y = y.rem_euclid(self.height as isize) as usize;
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl Texture for ImageTexture {
+    /// Bilinearly filtered lookup that wraps around the image edges. `v` runs
+    /// from the bottom of the image to the top.
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Color {
+        if self.pixels.is_empty() {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        (1.0 - tx) * (1.0 - ty) * self.texel(x0, y0)
+            + tx * (1.0 - ty) * self.texel(x0 + 1, y0)
+            + (1.0 - tx) * ty * self.texel(x0, y0 + 1)
+            + tx * ty * self.texel(x0 + 1, y0 + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_pixel(image: &ImageTexture, x: usize, y: usize, expected: (f64, f64, f64)) {
+        let c = image.pixel(x, y);
+        assert_eq!((c.r, c.g, c.b), expected);
+    }
+
+    #[test]
+    fn decodes_ascii_ppm_with_comments() {
+        let data = b"P3\n# a comment\n2 1 # trailing comment\n255\n255 0 51\n0 255 102\n";
+        let image = ImageTexture::decode(data).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_pixel(&image, 0, 0, (1.0, 0.0, 0.2));
+        assert_pixel(&image, 1, 0, (0.0, 1.0, 0.4));
+    }
+
+    #[test]
+    fn decodes_binary_ppm() {
+        let mut data = b"P6 1 2\n255\n".to_vec();
+        data.extend_from_slice(&[255, 0, 0, 0, 51, 255]);
+        let image = ImageTexture::decode(&data).unwrap();
+        assert_eq!((image.width(), image.height()), (1, 2));
+        assert_pixel(&image, 0, 0, (1.0, 0.0, 0.0));
+        assert_pixel(&image, 0, 1, (0.0, 0.2, 1.0));
+    }
+
+    #[test]
+    fn scales_by_max_value() {
+        let image = ImageTexture::decode(b"P3 1 1 15 15 5 0").unwrap();
+        assert_pixel(&image, 0, 0, (1.0, 5.0 / 15.0, 0.0));
+    }
+
+    #[test]
+    fn rejects_unsupported_max_values() {
+        assert!(ImageTexture::decode(b"P3 1 1 0 0 0 0").is_err());
+        assert!(ImageTexture::decode(b"P3 1 1 65535 0 0 0").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        assert!(ImageTexture::decode(b"P3 2 1 255 0 0 0 0 0").is_err());
+        assert!(ImageTexture::decode(b"P6 2 1 255\n\x00\x00\x00\x00").is_err());
+        assert!(ImageTexture::decode(b"P3 2").is_err());
+        assert!(ImageTexture::decode(b"").is_err());
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert!(ImageTexture::decode(b"P5 1 1 255\n\x00").is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::color::Color;
+
+/// Maps scene-referred linear radiance to display-referred linear values in
+/// [0, 1], before the sRGB transfer function is applied on output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapper {
+    /// Values above one are clipped.
+    Clamp,
+    /// `L / (1 + L)` on luminance, keeping hue.
+    Reinhard,
+    /// Reinhard with `white` as the luminance that maps to one.
+    ExtendedReinhard { white: f64 },
+    /// Narkowicz's curve fit of the ACES filmic reference transform.
+    AcesFilmic,
+    /// Blender's AgX view transform (polynomial approximation).
+    AgX,
+    /// John Hable's Uncharted 2 filmic curve.
+    Hable,
+}
+
+impl ToneMapper {
+    pub fn apply(&self, color: Color) -> Color {
+        match *self {
+            ToneMapper::Clamp => color,
+            ToneMapper::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            ToneMapper::ExtendedReinhard { white } => {
+                scale_luminance(color, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMapper::AcesFilmic => map_channels(color, aces_filmic),
+            ToneMapper::AgX => agx(color),
+            ToneMapper::Hable => {
+                const WHITE: f64 = 11.2;
+                const EXPOSURE_BIAS: f64 = 2.0;
+                let white_scale = 1.0 / hable_partial(WHITE);
+                map_channels(color, |c| hable_partial(EXPOSURE_BIAS * c) * white_scale)
+            }
+        }
+    }
+}
+
+fn map_channels(color: Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(color.r), f(color.g), f(color.b))
+}
+
+fn scale_luminance(color: Color, f: impl Fn(f64) -> f64) -> Color {
+    let luminance = color.luminance();
+    if luminance <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    (f(luminance) / luminance) * color
+}
+
+fn aces_filmic(x: f64) -> f64 {
+    // The fit expects its input pre-exposed by 0.6.
+    let x = 0.6 * x.max(0.0);
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+fn hable_partial(x: f64) -> f64 {
+    const A: f64 = 0.15;
+    const B: f64 = 0.50;
+    const C: f64 = 0.10;
+    const D: f64 = 0.20;
+    const E: f64 = 0.02;
+    const F: f64 = 0.30;
+    let x = x.max(0.0);
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+fn agx(color: Color) -> Color {
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+
+    // Inset into the AgX working space.
+    let c = mat3(
+        [
+            [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+            [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+            [0.0423756549057051, 0.0784336, 0.879142973793104],
+        ],
+        color,
+    );
+
+    // Log2 encoding followed by the default contrast curve.
+    let c = map_channels(c, |x| {
+        let x = (x.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    });
+
+    // Outset back to Rec. 709 and undo the curve's 2.2 display encoding.
+    let c = mat3(
+        [
+            [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+            [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+            [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+        ],
+        c,
+    );
+    map_channels(c, |x| x.max(0.0).powf(2.2))
+}
+
+fn mat3(m: [[f64; 3]; 3], c: Color) -> Color {
+    Color::new(
+        m[0][0] * c.r + m[0][1] * c.g + m[0][2] * c.b,
+        m[1][0] * c.r + m[1][1] * c.g + m[1][2] * c.b,
+        m[2][0] * c.r + m[2][1] * c.g + m[2][2] * c.b,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_grey(mapper: ToneMapper, input: f64, expected: f64) {
+        let c = mapper.apply(Color::new(input, input, input));
+        for channel in [c.r, c.g, c.b] {
+            assert!(
+                (channel - expected).abs() < 1e-6,
+                "{mapper:?}({input}) = {channel}, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn aces_filmic_matches_the_fit() {
+        assert_grey(ToneMapper::AcesFilmic, 0.0, 0.0);
+        assert_grey(ToneMapper::AcesFilmic, 0.18, 0.140120);
+        assert_grey(ToneMapper::AcesFilmic, 1.0, 0.673290);
+        assert_grey(ToneMapper::AcesFilmic, 4.0, 0.934211);
+    }
+
+    #[test]
+    fn hable_maps_white_point_to_one() {
+        assert_grey(ToneMapper::Hable, 0.0, 0.0);
+        assert_grey(ToneMapper::Hable, 0.18, 0.128338);
+        assert_grey(ToneMapper::Hable, 1.0, 0.492919);
+        assert_grey(ToneMapper::Hable, 5.6, 1.0);
+    }
+
+    #[test]
+    fn agx_maps_middle_grey_to_half_display_brightness() {
+        let c = ToneMapper::AgX.apply(Color::new(0.18, 0.18, 0.18));
+        for (channel, expected) in [(c.r, 0.214467), (c.g, 0.214533), (c.b, 0.214537)] {
+            assert!((channel - expected).abs() < 1e-6);
+        }
+        let c = ToneMapper::AgX.apply(Color::new(1.0, 0.0, 0.0));
+        for (channel, expected) in [(c.r, 0.719417), (c.g, 0.039488), (c.b, 0.039550)] {
+            assert!((channel - expected).abs() < 1e-6);
+        }
+        assert_grey(ToneMapper::AgX, 0.0, 0.0);
+    }
+
+    #[test]
+    fn reinhard_keeps_hue_and_white_point() {
+        let c = ToneMapper::Reinhard.apply(Color::new(2.0, 1.0, 0.5));
+        assert!((c.r / c.g - 2.0).abs() < 1e-12 && (c.g / c.b - 2.0).abs() < 1e-12);
+        assert_grey(ToneMapper::ExtendedReinhard { white: 4.0 }, 4.0, 1.0);
+    }
+}
@@ -5,6 +5,11 @@ pub fn degrees_to_radian(degree: f64) -> f64 {
     degree * PI / 180.0
 }
 
+/// Reseeds the generator behind `random_f64`, which is used for scene setup.
+pub fn seed_random(seed: u64) {
+    fastrand::seed(seed);
+}
+
 pub fn random_f64() -> f64 {
     fastrand::f64()
 }
@@ -12,3 +17,24 @@ pub fn random_f64() -> f64 {
 pub fn random_f64_range(min: f64, max: f64) -> f64 {
     min + (max - min) * random_f64()
 }
+
+/// Finalizer of the SplitMix64 generator; scrambles all bits of `v`.
+pub fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+pub fn hash_u64s(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |h, &v| {
+        mix_bits(h ^ v.wrapping_add(0x9e3779b97f4a7c15))
+    })
+}
+
+/// Maps a hash to a uniformly distributed value in [0, 1).
+pub fn hash_to_f64(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -22,7 +22,7 @@ impl Vec3 {
     }
 
     pub fn dot(u: Vec3, v: Vec3) -> f64 {
-        return u.x * v.x + u.y * v.y + u.z * v.z;
+        u.x * v.x + u.y * v.y + u.z * v.z
     }
 
     pub fn cross(u: Vec3, v: Vec3) -> Vec3 {
@@ -91,9 +91,9 @@ impl Vec3 {
         let on_unit_square = Self::random_unit_vector();
 
         if Self::dot(on_unit_square, normal) > 0.0 {
-            return on_unit_square;
+            on_unit_square
         } else {
-            return -on_unit_square;
+            -on_unit_square
         }
     }
 
@@ -105,7 +105,7 @@ impl Vec3 {
         let cos_theta = Self::dot(-uv, n).min(1.0);
         let r_out_perp = etai_over_etat * (uv + cos_theta * n);
         let r_out_parallel = -((1.0 - Self::length_squared(r_out_perp)).abs()).sqrt() * n;
-        return r_out_perp + r_out_parallel;
+        r_out_perp + r_out_parallel
     }
 }
 